@@ -0,0 +1,523 @@
+use std::time::{Duration, Instant};
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashSet,
+};
+use futures_lite::future;
+use rand::prelude::*;
+
+use crate::{
+    combat::{line_of_sight, Health, ATTACK_DAMAGE, ATTACK_RANGE},
+    fx::PlayerExplosion,
+    game::{CommandRequest, GameCommand, Player, Tile, TurnState, MAX_MOVE_DISTANCE},
+    hex::{HexCube, HEX_CUBE_DIRECTIONS},
+};
+
+const WIN_SCORE: f32 = 10000.0;
+
+// maximum number of actions simulated in a single MCTS playout
+const PLAYOUT_DEPTH: u32 = 8;
+
+#[derive(Clone, Copy, Debug)]
+pub enum SearchStrategy {
+    Greedy,
+    Minimax { max_depth: u32 },
+    Mcts { exploration: f32 },
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct AiController {
+    pub team: u32,
+    pub strategy: SearchStrategy,
+    pub time_budget: Duration,
+    last_turn: Option<u32>,
+}
+
+impl AiController {
+    pub fn new(team: u32, strategy: SearchStrategy, time_budget: Duration) -> Self {
+        AiController {
+            team,
+            strategy,
+            time_budget,
+            last_turn: None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct AiTask(Task<Option<GameCommand>>);
+
+#[derive(Clone, Copy, Debug)]
+pub struct AiUnit {
+    pub cube: HexCube,
+    pub team: u32,
+    pub health: f32,
+}
+
+// copy of the game state relevant for planning, so that the search can run off the main thread
+#[derive(Clone, Debug)]
+pub struct AiBoard {
+    pub tiles: HashSet<HexCube>,
+    pub players: Vec<AiUnit>,
+    pub active_team: u32,
+    pub num_teams: u32,
+}
+
+impl AiBoard {
+    fn player_at(&self, cube: &HexCube) -> Option<u32> {
+        self.players
+            .iter()
+            .find(|unit| unit.cube == *cube)
+            .map(|unit| unit.team)
+    }
+
+    fn next_team(&self) -> u32 {
+        (self.active_team + 1) % self.num_teams
+    }
+
+    fn num_players(&self, team: u32) -> usize {
+        self.players.iter().filter(|unit| unit.team == team).count()
+    }
+
+    fn is_decided(&self) -> bool {
+        (0..self.num_teams)
+            .filter(|team| self.num_players(*team) > 0)
+            .count()
+            <= 1
+    }
+
+    // cells a player on `from` can move to
+    pub fn reachable(&self, from: &HexCube) -> impl Iterator<Item = HexCube> + '_ {
+        let from = *from;
+        HEX_CUBE_DIRECTIONS
+            .iter()
+            .map(move |dir| from + *dir * MAX_MOVE_DISTANCE)
+            .filter(move |to| self.tiles.contains(to) && self.player_at(to).is_none())
+    }
+
+    // same rules as apply_game_commands_system: range and line of sight
+    pub fn can_attack(&self, from: &HexCube, target: &HexCube) -> bool {
+        from.distance(target) <= ATTACK_RANGE
+            && line_of_sight(*from, *target, |cube| {
+                !self.tiles.contains(cube) || self.player_at(cube).is_some()
+            })
+    }
+
+    pub fn legal_actions(&self) -> Vec<GameCommand> {
+        let mut actions = Vec::new();
+        for unit in &self.players {
+            if unit.team != self.active_team {
+                continue;
+            }
+            for target in &self.players {
+                if target.team != self.active_team && self.can_attack(&unit.cube, &target.cube) {
+                    actions.push(GameCommand::Attack {
+                        from: unit.cube,
+                        target: target.cube,
+                    });
+                }
+            }
+            for to in self.reachable(&unit.cube) {
+                actions.push(GameCommand::Move {
+                    from: unit.cube,
+                    to,
+                });
+            }
+        }
+        actions
+    }
+
+    // applies an action (or passes on None) and hands the turn to the next team, like
+    // apply_game_commands_system, where a turn is a single action as well
+    pub fn apply(&self, action: Option<&GameCommand>) -> AiBoard {
+        let mut board = self.clone();
+        match action {
+            Some(GameCommand::Move { from, to }) => {
+                if let Some(unit) = board.players.iter_mut().find(|unit| unit.cube == *from) {
+                    unit.cube = *to;
+                }
+            }
+            Some(GameCommand::Attack { target, .. }) => {
+                if let Some(unit) = board.players.iter_mut().find(|unit| unit.cube == *target) {
+                    unit.health -= ATTACK_DAMAGE;
+                }
+                board.players.retain(|unit| unit.health > 0.0);
+            }
+            Some(GameCommand::EndTurn { .. }) | None => (),
+        }
+        board.active_team = board.next_team();
+        board
+    }
+
+    // static evaluation from the point of view of `team`
+    pub fn evaluate(&self, team: u32) -> f32 {
+        let own = self.num_players(team);
+        let enemies = self.players.len() - own;
+        if enemies == 0 {
+            return WIN_SCORE;
+        }
+        if own == 0 {
+            return -WIN_SCORE;
+        }
+
+        let mut score = (own as f32 - enemies as f32) * 100.0;
+        for unit in &self.players {
+            let nearest_enemy = self
+                .players
+                .iter()
+                .filter(|other| other.team != unit.team)
+                .map(|other| unit.cube.distance(&other.cube))
+                .min()
+                .unwrap_or(0);
+            let mobility = self.reachable(&unit.cube).count() as f32;
+            let threatened = self
+                .players
+                .iter()
+                .any(|other| other.team != unit.team && self.can_attack(&other.cube, &unit.cube));
+
+            // being in range is good for the side to move and bad for the other one
+            let threat = match (threatened, self.active_team == unit.team) {
+                (true, true) => 20.0,
+                (true, false) => -30.0,
+                _ => 0.0,
+            };
+            let value = threat + mobility - nearest_enemy as f32 + unit.health * 0.5;
+            if unit.team == team {
+                score += value;
+            } else {
+                score -= value;
+            }
+        }
+        score
+    }
+}
+
+fn greedy(board: &AiBoard) -> Option<GameCommand> {
+    let team = board.active_team;
+    board
+        .legal_actions()
+        .into_iter()
+        .map(|action| (board.apply(Some(&action)).evaluate(team), action))
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, action)| action)
+}
+
+// negamax with alpha-beta pruning. Returns None when the deadline was hit.
+fn negamax(
+    board: &AiBoard,
+    depth: u32,
+    mut alpha: f32,
+    beta: f32,
+    deadline: Instant,
+) -> Option<f32> {
+    if Instant::now() >= deadline {
+        return None;
+    }
+    if depth == 0 || board.is_decided() {
+        return Some(board.evaluate(board.active_team));
+    }
+    let actions = board.legal_actions();
+    if actions.is_empty() {
+        return Some(-negamax(
+            &board.apply(None),
+            depth - 1,
+            -beta,
+            -alpha,
+            deadline,
+        )?);
+    }
+    let mut best = f32::NEG_INFINITY;
+    for action in &actions {
+        let score = -negamax(
+            &board.apply(Some(action)),
+            depth - 1,
+            -beta,
+            -alpha,
+            deadline,
+        )?;
+        best = best.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    Some(best)
+}
+
+// iterative deepening, so that there is always a result from the last completed depth
+fn minimax(board: &AiBoard, max_depth: u32, deadline: Instant) -> Option<GameCommand> {
+    let actions = board.legal_actions();
+    let mut best = greedy(board);
+    for depth in 1..=max_depth {
+        let mut best_at_depth = None;
+        let mut alpha = f32::NEG_INFINITY;
+        for action in &actions {
+            let score = match negamax(
+                &board.apply(Some(action)),
+                depth - 1,
+                f32::NEG_INFINITY,
+                -alpha,
+                deadline,
+            ) {
+                Some(score) => -score,
+                None => return best,
+            };
+            if score > alpha {
+                alpha = score;
+                best_at_depth = Some(action.clone());
+            }
+        }
+        if best_at_depth.is_some() {
+            best = best_at_depth;
+        }
+    }
+    best
+}
+
+struct MctsNode {
+    board: AiBoard,
+    action: Option<GameCommand>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<GameCommand>,
+    visits: u32,
+    // accumulated reward from the point of view of the team that made `action`
+    reward: f32,
+}
+
+impl MctsNode {
+    fn new(board: AiBoard, action: Option<GameCommand>, parent: Option<usize>) -> Self {
+        let untried = board.legal_actions();
+        MctsNode {
+            board,
+            action,
+            parent,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            reward: 0.0,
+        }
+    }
+}
+
+fn mcts(board: &AiBoard, exploration: f32, deadline: Instant) -> Option<GameCommand> {
+    let mut rng = rand::thread_rng();
+    let mut nodes = vec![MctsNode::new(board.clone(), None, None)];
+
+    while Instant::now() < deadline {
+        // selection
+        let mut node = 0;
+        while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+            let parent_visits = nodes[node].visits as f32;
+            node = *nodes[node]
+                .children
+                .iter()
+                .max_by(|a, b| {
+                    let uct = |i: usize| {
+                        let child = &nodes[i];
+                        child.reward / child.visits as f32
+                            + exploration * (parent_visits.ln() / child.visits as f32).sqrt()
+                    };
+                    uct(**a).total_cmp(&uct(**b))
+                })
+                .unwrap();
+        }
+
+        // expansion
+        if !nodes[node].untried.is_empty() {
+            let i = rng.gen_range(0..nodes[node].untried.len());
+            let action = nodes[node].untried.swap_remove(i);
+            let child_board = nodes[node].board.apply(Some(&action));
+            nodes.push(MctsNode::new(child_board, Some(action), Some(node)));
+            let child = nodes.len() - 1;
+            nodes[node].children.push(child);
+            node = child;
+        }
+
+        // playout
+        let mut playout = nodes[node].board.clone();
+        for _ in 0..PLAYOUT_DEPTH {
+            if playout.is_decided() {
+                break;
+            }
+            let actions = playout.legal_actions();
+            playout = playout.apply(actions.choose(&mut rng));
+        }
+        // squash the evaluation into [0, 1]
+        let reward_for = |team: u32| 1.0 / (1.0 + (-playout.evaluate(team) / 100.0).exp());
+
+        // backpropagation
+        let mut current = Some(node);
+        while let Some(i) = current {
+            let mover = match nodes[i].parent {
+                Some(parent) => nodes[parent].board.active_team,
+                None => board.active_team,
+            };
+            nodes[i].visits += 1;
+            nodes[i].reward += reward_for(mover);
+            current = nodes[i].parent;
+        }
+    }
+
+    nodes[0]
+        .children
+        .iter()
+        .max_by_key(|child| nodes[**child].visits)
+        .and_then(|child| nodes[*child].action.clone())
+        .or_else(|| greedy(board))
+}
+
+pub fn search(
+    board: &AiBoard,
+    strategy: SearchStrategy,
+    time_budget: Duration,
+) -> Option<GameCommand> {
+    let deadline = Instant::now() + time_budget;
+    match strategy {
+        SearchStrategy::Greedy => greedy(board),
+        SearchStrategy::Minimax { max_depth } => minimax(board, max_depth, deadline),
+        SearchStrategy::Mcts { exploration } => mcts(board, exploration, deadline),
+    }
+}
+
+pub fn ai_turn_system(
+    mut commands: Commands,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    turn_state: Res<TurnState>,
+    mut controllers: Query<(Entity, &mut AiController), Without<AiTask>>,
+    tile_query: Query<&HexCube, With<Tile>>,
+    player_query: Query<(&HexCube, &Player, &Health), Without<PlayerExplosion>>,
+) {
+    for (entity, mut controller) in controllers.iter_mut() {
+        if controller.team != turn_state.active_team
+            || controller.last_turn == Some(turn_state.turn)
+        {
+            continue;
+        }
+        controller.last_turn = Some(turn_state.turn);
+
+        let board = AiBoard {
+            tiles: tile_query.iter().cloned().collect(),
+            players: player_query
+                .iter()
+                .map(|(cube, player, health)| AiUnit {
+                    cube: *cube,
+                    team: player.team,
+                    health: health.current,
+                })
+                .collect(),
+            active_team: turn_state.active_team,
+            num_teams: turn_state.num_teams,
+        };
+        let strategy = controller.strategy;
+        let time_budget = controller.time_budget;
+        let task = thread_pool.spawn(async move { search(&board, strategy, time_budget) });
+        commands.entity(entity).insert(AiTask(task));
+    }
+}
+
+pub fn ai_poll_system(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &AiController, &mut AiTask)>,
+    mut command_requests: EventWriter<CommandRequest>,
+) {
+    for (entity, controller, mut task) in tasks.iter_mut() {
+        if let Some(action) = future::block_on(future::poll_once(&mut task.0)) {
+            commands.entity(entity).remove::<AiTask>();
+            info!("ai team {}: {:?}", controller.team, action);
+            // an accepted action ends the turn, the explicit end covers passing and rejected actions
+            if let Some(action) = action {
+                command_requests.send(CommandRequest(action));
+            }
+            command_requests.send(CommandRequest(GameCommand::EndTurn {
+                team: controller.team,
+            }));
+        }
+    }
+}
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(ai_turn_system).add_system(ai_poll_system);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(x: i32, team: u32, health: f32) -> AiUnit {
+        AiUnit {
+            cube: HexCube::new(x, -x, 0),
+            team,
+            health,
+        }
+    }
+
+    // a row of tiles with the given units on it
+    fn board(players: Vec<AiUnit>) -> AiBoard {
+        AiBoard {
+            tiles: (-3..=3).map(|x| HexCube::new(x, -x, 0)).collect(),
+            players,
+            active_team: 0,
+            num_teams: 2,
+        }
+    }
+
+    #[test]
+    fn evaluate_symmetry() {
+        for players in [
+            vec![unit(-2, 0, 100.0), unit(2, 1, 100.0)],
+            vec![unit(0, 0, 60.0), unit(1, 1, 100.0), unit(3, 1, 20.0)],
+            vec![unit(0, 0, 10.0)],
+        ] {
+            let board = board(players);
+            assert_eq!(board.evaluate(0), -board.evaluate(1));
+        }
+    }
+
+    // one attack kills the last enemy
+    fn winning_board() -> AiBoard {
+        board(vec![unit(0, 0, 100.0), unit(2, 1, ATTACK_DAMAGE)])
+    }
+
+    const WINNING_ATTACK: GameCommand = GameCommand::Attack {
+        from: HexCube { x: 0, y: 0, z: 0 },
+        target: HexCube { x: 2, y: -2, z: 0 },
+    };
+
+    #[test]
+    fn greedy_takes_winning_attack() {
+        let action = search(
+            &winning_board(),
+            SearchStrategy::Greedy,
+            Duration::from_secs(1),
+        );
+        assert_eq!(action, Some(WINNING_ATTACK));
+    }
+
+    #[test]
+    fn minimax_takes_winning_attack() {
+        let action = search(
+            &winning_board(),
+            SearchStrategy::Minimax { max_depth: 3 },
+            Duration::from_secs(5),
+        );
+        assert_eq!(action, Some(WINNING_ATTACK));
+    }
+
+    #[test]
+    fn mcts_returns_legal_action() {
+        let board = board(vec![unit(-2, 0, 100.0), unit(2, 1, 100.0)]);
+        let action = search(
+            &board,
+            SearchStrategy::Mcts { exploration: 1.4 },
+            Duration::from_millis(50),
+        );
+        assert!(board.legal_actions().contains(&action.unwrap()));
+    }
+}
@@ -1,53 +1,585 @@
-use bevy::{prelude::*, utils::HashMap};
-use bevy_rapier3d::prelude::*;
+use std::{
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    asset::FileAssetIo,
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use bevy_rapier3d::{
+    prelude::*,
+    rapier::{geometry::SharedShape, parry::transformation::vhacd::VHACDParameters},
+};
+use futures_lite::future;
 use multimap::MultiMap;
 
-#[derive(Default)]
+use crate::shape::HexPlane;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecompositionParams {
+    // maximum concavity of the resulting parts
+    pub concavity: f32,
+    // voxel resolution
+    pub resolution: u32,
+    pub max_convex_hulls: u32,
+}
+
+impl Default for DecompositionParams {
+    fn default() -> Self {
+        let params = VHACDParameters::default();
+        DecompositionParams {
+            concavity: params.concavity,
+            resolution: params.resolution,
+            max_convex_hulls: params.max_convex_hulls,
+        }
+    }
+}
+
+impl DecompositionParams {
+    fn vhacd(&self) -> VHACDParameters {
+        VHACDParameters {
+            concavity: self.concavity,
+            resolution: self.resolution,
+            max_convex_hulls: self.max_convex_hulls,
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ColliderStrategy {
+    TriMesh,
+    ConvexHull,
+    ConvexDecomposition(DecompositionParams),
+    // axis aligned bounding box of the mesh
+    Aabb,
+    // built from the dimensions alone, without looking at the mesh
+    HexPrism(HexPlane),
+}
+
+impl Default for ColliderStrategy {
+    fn default() -> Self {
+        ColliderStrategy::TriMesh
+    }
+}
+
+// Strategies are part of the cache key, equality and hash both go by the bits of the parameters
+// (i.e. 0.0 and -0.0 differ, NaN equals itself).
+impl PartialEq for ColliderStrategy {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.param_bits() == other.param_bits()
+    }
+}
+
+impl Eq for ColliderStrategy {}
+
+impl Hash for ColliderStrategy {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        self.param_bits().hash(state);
+    }
+}
+
+impl ColliderStrategy {
+    fn needs_mesh(&self) -> bool {
+        !matches!(self, ColliderStrategy::HexPrism(_))
+    }
+
+    fn param_bits(&self) -> [u32; 3] {
+        match self {
+            ColliderStrategy::ConvexDecomposition(params) => [
+                params.concavity.to_bits(),
+                params.resolution,
+                params.max_convex_hulls,
+            ],
+            ColliderStrategy::HexPrism(plane) => {
+                [plane.w.to_bits(), plane.h.to_bits(), plane.e.to_bits()]
+            }
+            _ => [0; 3],
+        }
+    }
+
+    pub fn build(&self, mesh: Option<&Mesh>) -> Option<Collider> {
+        match (self, mesh) {
+            (ColliderStrategy::HexPrism(plane), _) => Collider::convex_hull(&plane.corners()),
+            (ColliderStrategy::TriMesh, Some(mesh)) => Collider::bevy_mesh(mesh),
+            (ColliderStrategy::ConvexHull, Some(mesh)) => Collider::convex_hull(&positions(mesh)?),
+            (ColliderStrategy::ConvexDecomposition(params), Some(mesh)) => {
+                Collider::bevy_mesh_convex_decomposition_with_params(mesh, &params.vhacd())
+            }
+            (ColliderStrategy::Aabb, Some(mesh)) => {
+                let positions = positions(mesh)?;
+                let min = positions
+                    .iter()
+                    .fold(Vec3::splat(f32::MAX), |a, p| a.min(*p));
+                let max = positions
+                    .iter()
+                    .fold(Vec3::splat(f32::MIN), |a, p| a.max(*p));
+                let half = (max - min) / 2.0;
+                let cuboid = Collider::cuboid(half.x, half.y, half.z);
+                Some(Collider::compound(vec![(
+                    (min + max) / 2.0,
+                    Quat::IDENTITY,
+                    cuboid,
+                )]))
+            }
+            (_, None) => None,
+        }
+    }
+}
+
+fn positions(mesh: &Mesh) -> Option<Vec<Vec3>> {
+    match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+        VertexAttributeValues::Float32x3(positions) if !positions.is_empty() => {
+            Some(positions.iter().map(|p| Vec3::from(*p)).collect())
+        }
+        _ => None,
+    }
+}
+
+// bump when the way colliders are built changes, to invalidate the on-disk cache
+const COLLIDER_CACHE_VERSION: u32 = 2;
+
+// relative to the directory the asset folder is resolved from, not to the working directory
+const COLLIDER_CACHE_DIR: &str = "cache/colliders";
+
+// FNV-1a, unlike the std hashers guaranteed to give the same result on every launch
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+// identifies a collider by the data it is built from, i.e. vertices, indices and strategy
+fn collider_hash(meshes: &[Mesh], strategy: &ColliderStrategy) -> u64 {
+    let mut hasher = StableHasher::default();
+    COLLIDER_CACHE_VERSION.hash(&mut hasher);
+    strategy.hash(&mut hasher);
+    for mesh in meshes {
+        for p in positions(mesh).unwrap_or_default() {
+            p.to_array().map(f32::to_bits).hash(&mut hasher);
+        }
+        match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.hash(&mut hasher),
+            Some(Indices::U32(indices)) => indices.hash(&mut hasher),
+            None => (),
+        }
+    }
+    hasher.finish()
+}
+
+fn cache_file(dir: &Path, hash: u64) -> PathBuf {
+    dir.join(format!("{:016x}.collider", hash))
+}
+
+fn load_cached(dir: &Path, hash: u64) -> Option<Collider> {
+    let data = std::fs::read(cache_file(dir, hash)).ok()?;
+    match bincode::deserialize::<SharedShape>(&data) {
+        Ok(shape) => Some(Collider::from(shape)),
+        Err(err) => {
+            warn!("corrupt collider cache entry {:016x}: {}", hash, err);
+            None
+        }
+    }
+}
+
+fn store_cached(dir: &Path, hash: u64, collider: &Collider) {
+    let result = std::fs::create_dir_all(dir)
+        .map_err(|e| e.to_string())
+        .and_then(|_| bincode::serialize(&collider.raw).map_err(|e| e.to_string()))
+        .and_then(|data| std::fs::write(cache_file(dir, hash), data).map_err(|e| e.to_string()));
+    if let Err(err) = result {
+        warn!(
+            "failed to write collider cache entry {:016x}: {}",
+            hash, err
+        );
+    }
+}
+
+// runs on the task pool: look up the collider in the disk cache, build and store it on a miss
+fn build_cached(
+    cache_dir: Option<PathBuf>,
+    meshes: &[Mesh],
+    strategy: &ColliderStrategy,
+    build: impl FnOnce() -> Option<Collider>,
+) -> Option<Collider> {
+    let cache_dir = match cache_dir {
+        Some(cache_dir) => cache_dir,
+        None => return build(),
+    };
+    let hash = collider_hash(meshes, strategy);
+    if let Some(collider) = load_cached(&cache_dir, hash) {
+        return Some(collider);
+    }
+    let collider = build()?;
+    store_cached(&cache_dir, hash, &collider);
+    Some(collider)
+}
+
+// weak, so that cached colliders don't keep their meshes alive (and AssetEvent::Removed fires once
+// nothing else uses them)
+type ColliderKey = (Handle<Mesh>, ColliderStrategy);
+
 pub struct MeshColliderGenerator {
-    colliders: HashMap<Handle<Mesh>, Collider>,
-    pending: MultiMap<Handle<Mesh>, Entity>,
+    // None disables the on-disk cache
+    pub cache_dir: Option<PathBuf>,
+    colliders: HashMap<ColliderKey, Collider>,
+    pending: MultiMap<ColliderKey, Entity>,
+    // colliders under construction on the task pool
+    tasks: HashMap<ColliderKey, Task<Option<Collider>>>,
+    // compound colliders of scene roots under construction
+    scene_tasks: HashMap<Entity, Task<Option<Collider>>>,
+}
+
+impl Default for MeshColliderGenerator {
+    fn default() -> Self {
+        MeshColliderGenerator {
+            cache_dir: Some(FileAssetIo::get_root_path().join(COLLIDER_CACHE_DIR)),
+            colliders: default(),
+            pending: default(),
+            tasks: default(),
+            scene_tasks: default(),
+        }
+    }
+}
+
+impl MeshColliderGenerator {
+    // forget everything built from the mesh, running builds are cancelled by dropping their task
+    fn evict(&mut self, mesh: &Handle<Mesh>) {
+        self.colliders.retain(|(m, _), _| m != mesh);
+        self.tasks.retain(|(m, _), _| m != mesh);
+    }
 }
 
-#[derive(Component)]
+#[derive(Component, Default, Clone, Copy, Debug)]
 #[component(storage = "SparseSet")]
-pub struct AttachCollider;
+pub struct AttachCollider {
+    pub strategy: ColliderStrategy,
+}
+
+impl AttachCollider {
+    pub fn new(strategy: ColliderStrategy) -> Self {
+        AttachCollider { strategy }
+    }
+}
+
+// marks colliders built from the entity's mesh, so that they can be rebuilt when the mesh changes
+#[derive(Component, Clone, Copy, Debug)]
+pub struct AutoCollider {
+    pub strategy: ColliderStrategy,
+}
+
+// only entities that still wait for a collider get one, they may have been despawned or given
+// another collider in the meantime.
+fn insert_collider(
+    commands: &mut Commands,
+    waiting: &Query<&AttachCollider>,
+    entities: &[Entity],
+    collider: &Collider,
+) {
+    for entity in entities {
+        if let Ok(attach) = waiting.get(*entity) {
+            commands
+                .entity(*entity)
+                .insert(collider.clone())
+                .insert(AutoCollider {
+                    strategy: attach.strategy,
+                })
+                .remove::<AttachCollider>();
+        }
+    }
+}
 
 #[allow(clippy::type_complexity)]
 pub fn attach_collider_system(
     mut commands: Commands,
     mut state: ResMut<MeshColliderGenerator>,
+    thread_pool: Res<AsyncComputeTaskPool>,
     mut mesh_events: EventReader<AssetEvent<Mesh>>,
     meshes: Res<Assets<Mesh>>,
-    query: Query<(Entity, &Handle<Mesh>), (Added<AttachCollider>, Without<Collider>)>,
+    query: Query<
+        (Entity, &Handle<Mesh>, &AttachCollider),
+        (Added<AttachCollider>, Without<Collider>),
+    >,
+    waiting: Query<&AttachCollider>,
+    auto_query: Query<(Entity, &Handle<Mesh>, &AutoCollider)>,
 ) {
-    for (entity, mesh) in query.iter() {
-        state.pending.insert(mesh.clone(), entity);
+    let mut ready = Vec::new();
+    for (entity, mesh, attach) in query.iter() {
+        let key = (mesh.clone_weak(), attach.strategy);
+        if let Some(collider) = state.colliders.get(&key) {
+            commands
+                .entity(entity)
+                .insert(collider.clone())
+                .insert(AutoCollider {
+                    strategy: attach.strategy,
+                })
+                .remove::<AttachCollider>();
+            continue;
+        }
+        // meshes that are already loaded will not see another Created event
+        if !attach.strategy.needs_mesh() || meshes.get(mesh).is_some() {
+            ready.push(key.clone());
+        }
+        state.pending.insert(key, entity);
     }
 
     for event in mesh_events.iter() {
-        if let AssetEvent::Created { handle } = event {
-            if let Some(v) = state.pending.remove(handle) {
-                let collider = match state.colliders.entry(handle.clone()) {
-                    bevy::utils::hashbrown::hash_map::Entry::Occupied(e) => e.get().clone(),
-                    bevy::utils::hashbrown::hash_map::Entry::Vacant(e) => {
-                        if let Some(mesh) = meshes.get(handle) {
-                            // TODO: calculate decomposition in background
-                            // TODO2: meh think again, the hex tiles are already convex...
-                            // let collider = Collider::bevy_mesh_convex_decomposition(mesh).unwrap();
-                            let collider = Collider::bevy_mesh(mesh).unwrap();
-                            info!("convex decomposition done.");
-                            e.insert(collider).clone()
-                        } else {
-                            panic!("could not get mesh instance after Created event!?");
-                        }
+        match event {
+            AssetEvent::Created { handle } => {
+                ready.extend(
+                    state
+                        .pending
+                        .keys()
+                        .filter(|(mesh, _)| mesh == handle)
+                        .cloned(),
+                );
+            }
+            AssetEvent::Modified { handle } => {
+                // drop stale colliders (and builds in progress) and rebuild them for everyone using
+                // the mesh
+                state.evict(handle);
+                for (entity, mesh, auto) in auto_query.iter() {
+                    if mesh != handle || !auto.strategy.needs_mesh() {
+                        continue;
                     }
-                };
+                    commands.entity(entity).insert(AttachCollider {
+                        strategy: auto.strategy,
+                    });
+                    state
+                        .pending
+                        .insert((mesh.clone_weak(), auto.strategy), entity);
+                }
+                ready.extend(
+                    state
+                        .pending
+                        .keys()
+                        .filter(|(mesh, _)| mesh == handle)
+                        .cloned(),
+                );
+            }
+            AssetEvent::Removed { handle } => {
+                state.evict(handle);
+                let keys: Vec<_> = state
+                    .pending
+                    .keys()
+                    .filter(|(mesh, _)| mesh == handle)
+                    .cloned()
+                    .collect();
+                for key in keys {
+                    state.pending.remove(&key);
+                }
+            }
+        }
+    }
+
+    for key in ready {
+        if !state.pending.contains_key(&key) || state.tasks.contains_key(&key) {
+            continue;
+        }
+        if let Some(collider) = state.colliders.get(&key).cloned() {
+            if let Some(v) = state.pending.remove(&key) {
+                insert_collider(&mut commands, &waiting, &v, &collider);
+            }
+            continue;
+        }
+        let strategy = key.1;
+        let mesh = match meshes.get(&key.0) {
+            Some(mesh) if strategy.needs_mesh() => Some(mesh.clone()),
+            None if strategy.needs_mesh() => {
+                warn!("mesh {:?} not available for collider", key.0);
+                continue;
+            }
+            _ => None,
+        };
+        // especially the convex decomposition can take a while
+        let cache_dir = state.cache_dir.clone();
+        let task = thread_pool.spawn(async move {
+            let parts: Vec<Mesh> = mesh.iter().cloned().collect();
+            build_cached(cache_dir, &parts, &strategy, || {
+                strategy.build(mesh.as_ref())
+            })
+        });
+        state.tasks.insert(key, task);
+    }
+}
+
+// hands finished colliders to all entities waiting for the mesh
+pub fn collider_task_system(
+    mut commands: Commands,
+    mut state: ResMut<MeshColliderGenerator>,
+    waiting: Query<&AttachCollider>,
+) {
+    let mut done = Vec::new();
+    for (key, task) in state.tasks.iter_mut() {
+        if let Some(collider) = future::block_on(future::poll_once(task)) {
+            done.push((key.clone(), collider));
+        }
+    }
+
+    for (key, collider) in done {
+        state.tasks.remove(&key);
+        let collider = match collider {
+            Some(collider) => collider,
+            None => {
+                warn!("failed to build collider {:?} for mesh {:?}", key.1, key.0);
+                state.pending.remove(&key);
+                continue;
+            }
+        };
+        info!("collider {:?} for mesh {:?} done.", key.1, key.0);
+
+        if let Some(v) = state.pending.remove(&key) {
+            insert_collider(&mut commands, &waiting, &v, &collider);
+        }
+        state.colliders.insert(key, collider);
+    }
+}
+
+// (transform relative to the scene root, mesh) of all meshes below entity
+fn collect_meshes(
+    entity: Entity,
+    matrix: Mat4,
+    children_query: &Query<&Children>,
+    node_query: &Query<(&Transform, Option<&Handle<Mesh>>)>,
+    out: &mut Vec<(Mat4, Handle<Mesh>)>,
+) {
+    let children = match children_query.get(entity) {
+        Ok(children) => children,
+        Err(_) => return,
+    };
+    for child in children.iter() {
+        let (transform, mesh) = match node_query.get(*child) {
+            Ok(node) => node,
+            Err(_) => continue,
+        };
+        let matrix = matrix * transform.compute_matrix();
+        if let Some(mesh) = mesh {
+            out.push((matrix, mesh.clone()));
+        }
+        collect_meshes(*child, matrix, children_query, node_query, out);
+    }
+}
+
+// the mesh with its vertices moved into the space of the scene root. Baking the transform into the
+// vertices also takes care of scaled nodes, which a compound collider could not express.
+fn transformed_mesh(mesh: &Mesh, matrix: Mat4) -> Option<Mesh> {
+    let positions: Vec<[f32; 3]> = positions(mesh)?
+        .iter()
+        .map(|p| matrix.transform_point3(*p).to_array())
+        .collect();
+    let mut mesh = mesh.clone();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    Some(mesh)
+}
+
+// Entities with AttachCollider but without a mesh of their own, e.g. the parent of a spawned glTF
+// scene. Once the scene is instantiated and all its meshes are loaded, a compound collider is built
+// from all meshes below the entity.
+#[allow(clippy::type_complexity)]
+pub fn attach_scene_collider_system(
+    mut state: ResMut<MeshColliderGenerator>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    meshes: Res<Assets<Mesh>>,
+    query: Query<(Entity, &AttachCollider), (Without<Handle<Mesh>>, Without<Collider>)>,
+    children_query: Query<&Children>,
+    node_query: Query<(&Transform, Option<&Handle<Mesh>>)>,
+) {
+    for (entity, attach) in query.iter() {
+        if state.scene_tasks.contains_key(&entity) {
+            continue;
+        }
+        let strategy = attach.strategy;
+        if !strategy.needs_mesh() {
+            let task = thread_pool.spawn(async move { strategy.build(None) });
+            state.scene_tasks.insert(entity, task);
+            continue;
+        }
+
+        let mut nodes = Vec::new();
+        collect_meshes(
+            entity,
+            Mat4::IDENTITY,
+            &children_query,
+            &node_query,
+            &mut nodes,
+        );
+        if nodes.is_empty() {
+            // scene not instantiated yet
+            continue;
+        }
+        if nodes.iter().any(|(_, mesh)| meshes.get(mesh).is_none()) {
+            // still loading
+            continue;
+        }
+        let parts: Vec<Mesh> = nodes
+            .iter()
+            .filter_map(|(matrix, mesh)| transformed_mesh(meshes.get(mesh)?, *matrix))
+            .collect();
+
+        let cache_dir = state.cache_dir.clone();
+        let task = thread_pool.spawn(async move {
+            build_cached(cache_dir, &parts, &strategy, || {
+                let mut colliders: Vec<_> = parts
+                    .iter()
+                    .filter_map(|mesh| strategy.build(Some(mesh)))
+                    .map(|collider| (Vec3::ZERO, Quat::IDENTITY, collider))
+                    .collect();
+                match colliders.len() {
+                    0 => None,
+                    1 => colliders.pop().map(|(_, _, collider)| collider),
+                    _ => Some(Collider::compound(colliders)),
+                }
+            })
+        });
+        state.scene_tasks.insert(entity, task);
+    }
+}
+
+pub fn scene_collider_task_system(
+    mut commands: Commands,
+    mut state: ResMut<MeshColliderGenerator>,
+    waiting: Query<&AttachCollider>,
+) {
+    let mut done = Vec::new();
+    for (entity, task) in state.scene_tasks.iter_mut() {
+        if let Some(collider) = future::block_on(future::poll_once(task)) {
+            done.push((*entity, collider));
+        }
+    }
 
-                for entity in v.iter() {
-                    commands
-                        .entity(*entity)
-                        .insert(collider.clone())
-                        .remove::<AttachCollider>();
+    for (entity, collider) in done {
+        state.scene_tasks.remove(&entity);
+        match collider {
+            Some(collider) => {
+                info!("scene collider for {:?} done.", entity);
+                insert_collider(&mut commands, &waiting, &[entity], &collider);
+            }
+            None => {
+                warn!("failed to build scene collider for {:?}", entity);
+                // don't retry every frame
+                if waiting.get(entity).is_ok() {
+                    commands.entity(entity).remove::<AttachCollider>();
                 }
             }
         }
@@ -59,6 +591,223 @@ pub struct AutoColliderPlugin;
 impl Plugin for AutoColliderPlugin {
     fn build(&self, app: &mut App) {
         app.add_system(attach_collider_system)
+            .add_system(collider_task_system.after(attach_collider_system))
+            .add_system(attach_scene_collider_system)
+            .add_system(scene_collider_task_system.after(attach_scene_collider_system))
             .init_resource::<MeshColliderGenerator>();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::asset::AssetPlugin;
+
+    use super::*;
+
+    const MAX_FRAMES: usize = 500;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Mesh>()
+            .add_plugin(AutoColliderPlugin)
+            .insert_resource(MeshColliderGenerator {
+                cache_dir: None,
+                ..default()
+            });
+        app
+    }
+
+    // colliders are built on the task pool, so this may take a few frames
+    fn run_until(app: &mut App, condition: impl Fn(&mut App) -> bool) -> bool {
+        for _ in 0..MAX_FRAMES {
+            app.update();
+            if condition(app) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+
+    fn add_mesh(app: &mut App) -> Handle<Mesh> {
+        let mut meshes = app.world.get_resource_mut::<Assets<Mesh>>().unwrap();
+        meshes.add(shape::Cube { size: 1.0 }.into())
+    }
+
+    fn spawn_attached(app: &mut App, mesh: &Handle<Mesh>) -> Entity {
+        app.world
+            .spawn()
+            .insert(mesh.clone())
+            .insert(AttachCollider::new(ColliderStrategy::TriMesh))
+            .id()
+    }
+
+    fn has_collider(app: &App, entity: Entity) -> bool {
+        let entity = app.world.entity(entity);
+        entity.contains::<Collider>()
+            && entity.contains::<AutoCollider>()
+            && !entity.contains::<AttachCollider>()
+    }
+
+    fn half_extent(app: &App, entity: Entity) -> f32 {
+        let collider = app.world.get::<Collider>(entity).unwrap();
+        collider.raw.compute_local_aabb().half_extents().x
+    }
+
+    fn generator(app: &App) -> &MeshColliderGenerator {
+        app.world.get_resource::<MeshColliderGenerator>().unwrap()
+    }
+
+    // the mesh was added (and its Created event consumed) before the entity asked for a collider
+    #[test]
+    fn already_loaded_mesh() {
+        let mut app = test_app();
+        let mesh = add_mesh(&mut app);
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let entity = spawn_attached(&mut app, &mesh);
+        assert!(run_until(&mut app, |app| has_collider(app, entity)));
+        assert!((half_extent(&app, entity) - 0.5).abs() < 1e-4);
+        let state = generator(&app);
+        assert!(state.pending.is_empty());
+        assert!(state.tasks.is_empty());
+        assert_eq!(state.colliders.len(), 1);
+    }
+
+    #[test]
+    fn cache_hit() {
+        let mut app = test_app();
+        let mesh = add_mesh(&mut app);
+        let first = spawn_attached(&mut app, &mesh);
+        assert!(run_until(&mut app, |app| has_collider(app, first)));
+
+        // served from the built colliders within one frame, without starting another build
+        let second = spawn_attached(&mut app, &mesh);
+        app.update();
+        assert!(has_collider(&app, second));
+        let state = generator(&app);
+        assert!(state.pending.is_empty());
+        assert!(state.tasks.is_empty());
+        assert_eq!(state.colliders.len(), 1);
+    }
+
+    #[test]
+    fn modified_mesh_is_rebuilt() {
+        let mut app = test_app();
+        let mesh = add_mesh(&mut app);
+        let entity = spawn_attached(&mut app, &mesh);
+        assert!(run_until(&mut app, |app| has_collider(app, entity)));
+
+        let mut meshes = app.world.get_resource_mut::<Assets<Mesh>>().unwrap();
+        let scaled = shape::Cube { size: 2.0 }.into();
+        *meshes.get_mut(&mesh).unwrap() = scaled;
+
+        assert!(run_until(&mut app, |app| {
+            has_collider(app, entity) && (half_extent(app, entity) - 1.0).abs() < 1e-4
+        }));
+        let state = generator(&app);
+        assert!(state.pending.is_empty());
+        assert!(state.tasks.is_empty());
+        assert_eq!(state.colliders.len(), 1);
+    }
+
+    #[test]
+    fn removed_mesh_is_evicted() {
+        let mut app = test_app();
+        let mesh = add_mesh(&mut app);
+        let entity = spawn_attached(&mut app, &mesh);
+        assert!(run_until(&mut app, |app| has_collider(app, entity)));
+
+        // the modification makes the entity wait for a rebuild, the removal in the same frame
+        // has to cancel it
+        let mut meshes = app.world.get_resource_mut::<Assets<Mesh>>().unwrap();
+        meshes.get_mut(&mesh).unwrap();
+        meshes.remove(&mesh);
+
+        assert!(run_until(&mut app, |app| {
+            app.world.entity(entity).contains::<AttachCollider>()
+        }));
+        let state = generator(&app);
+        assert!(state.pending.is_empty());
+        assert!(state.tasks.is_empty());
+        assert!(state.colliders.is_empty());
+    }
+
+    // the cache only holds weak handles, so dropping the last user frees the mesh, which in turn
+    // evicts its collider
+    #[test]
+    fn unused_mesh_is_evicted() {
+        let mut app = test_app();
+        let mesh = add_mesh(&mut app);
+        let entity = spawn_attached(&mut app, &mesh);
+        assert!(run_until(&mut app, |app| has_collider(app, entity)));
+        assert_eq!(generator(&app).colliders.len(), 1);
+
+        app.world.despawn(entity);
+        drop(mesh);
+
+        assert!(run_until(&mut app, |app| generator(app)
+            .colliders
+            .is_empty()));
+        let meshes = app.world.get_resource::<Assets<Mesh>>().unwrap();
+        assert_eq!(meshes.len(), 0);
+        let state = generator(&app);
+        assert!(state.pending.is_empty());
+        assert!(state.tasks.is_empty());
+    }
+
+    #[test]
+    fn strategy_eq_agrees_with_hash() {
+        let hash = |strategy: &ColliderStrategy| {
+            let mut hasher = StableHasher::default();
+            strategy.hash(&mut hasher);
+            hasher.finish()
+        };
+        let decomposition = |concavity| {
+            ColliderStrategy::ConvexDecomposition(DecompositionParams {
+                concavity,
+                ..default()
+            })
+        };
+        for (a, b) in [
+            (decomposition(0.0), decomposition(-0.0)),
+            (decomposition(f32::NAN), decomposition(f32::NAN)),
+            (decomposition(0.1), decomposition(0.1)),
+            (ColliderStrategy::TriMesh, ColliderStrategy::ConvexHull),
+        ] {
+            assert_eq!(a == b, hash(&a) == hash(&b), "{:?} {:?}", a, b);
+        }
+        assert_eq!(decomposition(f32::NAN), decomposition(f32::NAN));
+        assert_ne!(decomposition(0.0), decomposition(-0.0));
+    }
+
+    // a cache entry that doesn't deserialize is rebuilt and overwritten
+    #[test]
+    fn corrupt_cache_entry_is_rebuilt() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("game2_collider_cache_{}", std::process::id()));
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        let meshes = [Mesh::from(shape::Cube { size: 1.0 })];
+        let strategy = ColliderStrategy::ConvexHull;
+        let hash = collider_hash(&meshes, &strategy);
+        std::fs::write(cache_file(&cache_dir, hash), b"garbage").unwrap();
+
+        let mut built = false;
+        let collider = build_cached(Some(cache_dir.clone()), &meshes, &strategy, || {
+            built = true;
+            Some(Collider::cuboid(0.5, 0.5, 0.5))
+        });
+        let reloaded = load_cached(&cache_dir, hash);
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+
+        assert!(built);
+        assert!((collider.unwrap().raw.compute_local_aabb().half_extents().x - 0.5).abs() < 1e-4);
+        assert!(reloaded.is_some());
+    }
+}
@@ -0,0 +1,327 @@
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashSet};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    destruction::DestroyTile,
+    fx::{player_explosion_system, PlayerExplosion},
+    game::{GameSystem, Player, RemoteAuthority, Tile},
+    hex::{CubeLinedraw, HexCube},
+};
+
+// maximum hex distance between attacker and target
+pub const ATTACK_RANGE: i32 = 2;
+pub const ATTACK_DAMAGE: f32 = 40.0;
+pub const PLAYER_HEALTH: f32 = 100.0;
+
+// rigid bodies within this (world space) distance of an explosion are pushed away
+pub const EXPLOSION_IMPULSE_RANGE: f32 = 1.5;
+// time until a player caught in a chain reaction detonates
+pub const CHAIN_REACTION_DELAY: f32 = 0.3;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::new(PLAYER_HEALTH)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub source: Option<Entity>,
+    pub amount: f32,
+}
+
+// true if none of the cells strictly between `from` and `to` is blocked
+pub fn line_of_sight(from: HexCube, to: HexCube, is_blocked: impl Fn(&HexCube) -> bool) -> bool {
+    CubeLinedraw::new(from, to)
+        .skip(1)
+        .filter(|cube| *cube != to)
+        .all(|cube| !is_blocked(&cube))
+}
+
+pub struct ExplosionSettings {
+    // in hex cells
+    pub radius: i32,
+    pub damage: f32,
+    pub impulse: f32,
+    // push players on adjacent cells one cell further away
+    pub knockback: bool,
+    // players in range detonate as well, instead of taking damage
+    pub chain_reactions: bool,
+    // the tile below the explosion is launched as a dynamic body
+    pub destroy_tiles: bool,
+}
+
+impl Default for ExplosionSettings {
+    fn default() -> Self {
+        ExplosionSettings {
+            radius: 1,
+            damage: 30.0,
+            impulse: 0.5,
+            knockback: true,
+            chain_reactions: false,
+            destroy_tiles: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExplosionEvent {
+    pub center: HexCube,
+    pub pos: Vec3,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn explosion_system(
+    mut commands: Commands,
+    mut events: EventReader<ExplosionEvent>,
+    settings: Res<ExplosionSettings>,
+    remote_authority: Option<Res<RemoteAuthority>>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut destroy_events: EventWriter<DestroyTile>,
+    tile_query: Query<&HexCube, With<Tile>>,
+    mut player_query: Query<
+        (Entity, &mut HexCube, &mut Transform),
+        (With<Player>, Without<PlayerExplosion>, Without<Tile>),
+    >,
+    body_query: Query<(Entity, &GlobalTransform, &RigidBody)>,
+) {
+    for event in events.iter() {
+        // replicated clients only push things around, the server resolves the rest
+        let authority = remote_authority.is_none();
+        let tiles: HashSet<HexCube> = tile_query.iter().cloned().collect();
+        let mut occupied: HashSet<HexCube> =
+            player_query.iter().map(|(_, cube, _)| *cube).collect();
+
+        // resolved in cube order, the query order may differ between peers and the knockback of one
+        // player decides which cells are free for the next
+        let mut affected: Vec<(Entity, HexCube)> = player_query
+            .iter()
+            .filter(|(_, cube, _)| {
+                let distance = cube.distance(&event.center);
+                authority && distance > 0 && distance <= settings.radius
+            })
+            .map(|(entity, cube, _)| (entity, *cube))
+            .collect();
+        affected.sort_by_key(|(_, cube)| (cube.x, cube.y, cube.z));
+
+        for (entity, _) in affected {
+            let (_, mut cube, mut transform) = player_query.get_mut(entity).unwrap();
+            let distance = cube.distance(&event.center);
+            if settings.chain_reactions {
+                commands.entity(entity).insert(PlayerExplosion {
+                    time_left: CHAIN_REACTION_DELAY,
+                });
+                continue;
+            }
+            damage_events.send(DamageEvent {
+                target: entity,
+                source: None,
+                amount: settings.damage,
+            });
+
+            if settings.knockback && distance == 1 {
+                let to = *cube + (*cube - event.center);
+                if tiles.contains(&to) && !occupied.contains(&to) {
+                    occupied.remove(&cube);
+                    occupied.insert(to);
+                    *cube = to;
+                    let v = to.to_odd_r_screen().extend(0.0).xzy();
+                    transform.translation = v + Vec3::Y * 0.2;
+                }
+            }
+        }
+
+        for (entity, transform, body) in body_query.iter() {
+            if *body != RigidBody::Dynamic {
+                continue;
+            }
+            let d = transform.translation - event.pos;
+            let falloff = 1.0 - d.length() / EXPLOSION_IMPULSE_RANGE;
+            if falloff <= 0.0 {
+                continue;
+            }
+            // always with an upward component, so that things are lifted off the ground
+            let dir = (d.normalize_or_zero() + Vec3::Y * 0.5).normalize();
+            commands.entity(entity).insert(ExternalImpulse {
+                impulse: dir * settings.impulse * falloff,
+                torque_impulse: Vec3::ZERO,
+            });
+        }
+
+        if authority && settings.destroy_tiles && tiles.contains(&event.center) {
+            destroy_events.send(DestroyTile {
+                cube: event.center,
+                impulse: Vec3::Y * settings.impulse,
+            });
+        }
+    }
+}
+
+pub fn damage_system(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut query: Query<&mut Health, Without<PlayerExplosion>>,
+) {
+    for event in damage_events.iter() {
+        if let Ok(mut health) = query.get_mut(event.target) {
+            if health.is_dead() {
+                // already received lethal damage this frame
+                continue;
+            }
+            health.current -= event.amount;
+            info!(
+                "damage {:?}: {} -> {}/{}",
+                event.target, event.amount, health.current, health.max
+            );
+            if health.is_dead() {
+                commands
+                    .entity(event.target)
+                    .insert(PlayerExplosion { time_left: 1.0 });
+            }
+        }
+    }
+}
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ExplosionSettings>()
+            .add_event::<DamageEvent>()
+            .add_event::<ExplosionEvent>()
+            // in the same frame as the countdown, which under lockstep only expires in tick frames
+            .add_system(
+                explosion_system
+                    .after(GameSystem::ApplyCommands)
+                    .after(player_explosion_system),
+            )
+            .add_system(
+                damage_system
+                    .after(GameSystem::ApplyCommands)
+                    .after(explosion_system),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{apply_game_commands_system, GameCommand, TurnState};
+
+    #[test]
+    fn line_of_sight_clear_and_blocked() {
+        let from = HexCube::new(0, 0, 0);
+        let to = HexCube::new(3, -3, 0);
+        assert!(line_of_sight(from, to, |_| false));
+        // the end points themselves never block
+        assert!(line_of_sight(from, to, |cube| *cube == from || *cube == to));
+        assert!(!line_of_sight(from, to, |cube| *cube == HexCube::new(2, -2, 0)));
+        // adjacent cells have nothing in between
+        assert!(line_of_sight(from, HexCube::new(1, -1, 0), |_| true));
+    }
+
+    fn combat_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<TurnState>()
+            .add_event::<GameCommand>()
+            .add_event::<DamageEvent>()
+            .add_system(apply_game_commands_system.label(GameSystem::ApplyCommands))
+            .add_system(damage_system.after(GameSystem::ApplyCommands));
+        for x in -4..=4 {
+            app.world
+                .spawn()
+                .insert(HexCube::new(x, -x, 0))
+                .insert(Tile);
+        }
+        app
+    }
+
+    fn spawn_player(app: &mut App, x: i32, team: u32, health: f32) -> Entity {
+        app.world
+            .spawn()
+            .insert(HexCube::new(x, -x, 0))
+            .insert(Player { team })
+            .insert(Health {
+                current: health,
+                max: PLAYER_HEALTH,
+            })
+            .insert(Transform::default())
+            .id()
+    }
+
+    fn attack(app: &mut App, from: i32, target: i32) {
+        app.world
+            .get_resource_mut::<Events<GameCommand>>()
+            .unwrap()
+            .send(GameCommand::Attack {
+                from: HexCube::new(from, -from, 0),
+                target: HexCube::new(target, -target, 0),
+            });
+        app.update();
+    }
+
+    fn health(app: &App, entity: Entity) -> f32 {
+        app.world.get::<Health>(entity).unwrap().current
+    }
+
+    #[test]
+    fn attack_range() {
+        let mut app = combat_app();
+        spawn_player(&mut app, 0, 0, PLAYER_HEALTH);
+        let far = spawn_player(&mut app, ATTACK_RANGE + 1, 1, PLAYER_HEALTH);
+        let near = spawn_player(&mut app, -ATTACK_RANGE, 1, PLAYER_HEALTH);
+
+        attack(&mut app, 0, ATTACK_RANGE + 1);
+        assert_eq!(health(&app, far), PLAYER_HEALTH);
+        // a rejected attack does not end the turn
+        assert_eq!(
+            app.world.get_resource::<TurnState>().unwrap().active_team,
+            0
+        );
+
+        attack(&mut app, 0, -ATTACK_RANGE);
+        assert_eq!(health(&app, near), PLAYER_HEALTH - ATTACK_DAMAGE);
+    }
+
+    #[test]
+    fn lethal_damage_starts_explosion() {
+        let mut app = combat_app();
+        spawn_player(&mut app, 0, 0, PLAYER_HEALTH);
+        let target = spawn_player(&mut app, 1, 1, ATTACK_DAMAGE);
+
+        attack(&mut app, 0, 1);
+        assert!(health(&app, target) <= 0.0);
+        assert!(app.world.get::<PlayerExplosion>(target).is_some());
+
+        // no further damage while exploding
+        app.world
+            .get_resource_mut::<Events<DamageEvent>>()
+            .unwrap()
+            .send(DamageEvent {
+                target,
+                source: None,
+                amount: ATTACK_DAMAGE,
+            });
+        app.update();
+        assert_eq!(health(&app, target), 0.0);
+    }
+}
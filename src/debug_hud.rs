@@ -11,7 +11,11 @@ use bevy_egui::{
     EguiContext,
 };
 
-use crate::property::{PropertyRegistry, PropertyUpdateEvent, PropertyValue};
+use crate::{
+    physics_config, physics_debug,
+    property::{PropertyRegistry, PropertyUpdateEvent, PropertyValue},
+    sim_time::SimTime,
+};
 
 fn mag_to_str(mag: i32) -> &'static str {
     match mag {
@@ -49,6 +53,28 @@ pub fn hud_egui_setup_system(mut commands: Commands, mut hud_order: ResMut<HudOr
     //     .insert(HudElement::TextWithSource(HudSrc::RenderStatus))
     //     .insert(hud_order.next().in_group(hud_group));
 
+    let hud_group = "2. Physics";
+    for property_name in [
+        physics_debug::PHYSICS_DEBUG_PROPERTY,
+        physics_debug::PHYSICS_DEBUG_CONTACTS_PROPERTY,
+        physics_debug::PHYSICS_DEBUG_SLEEPING_PROPERTY,
+        physics_debug::PHYSICS_DEBUG_HIGHLIGHT_PROPERTY,
+    ] {
+        commands
+            .spawn()
+            .insert(HudElement::ToggleButtonProperty(
+                property_name.into(),
+                "on".into(),
+                "off".into(),
+            ))
+            .insert(hud_order.next().in_group(hud_group));
+    }
+
+    commands
+        .spawn()
+        .insert(HudElement::SimTimeControls)
+        .insert(hud_order.next().in_group("3. Time"));
+
     commands.spawn().insert(HudPlotDiagnostic::new(
         FrameTimeDiagnosticsPlugin::FPS,
         "fps",
@@ -57,6 +83,30 @@ pub fn hud_egui_setup_system(mut commands: Commands, mut hud_order: ResMut<HudOr
         EntityCountDiagnosticsPlugin::ENTITY_COUNT,
         "entity count",
     ));
+    commands.spawn().insert(HudPlotDiagnostic::new(
+        crate::fx::pool::FX_PARTICLES_ACTIVE,
+        "fx particles",
+    ));
+    commands.spawn().insert(HudPlotDiagnostic::new(
+        crate::fx::pool::FX_PARTICLES_FREE,
+        "fx particles free",
+    ));
+}
+
+// make properties registered by other plugins editable in the hud
+pub fn hud_edit_properties_system(
+    mut commands: Commands,
+    mut hud_order: ResMut<HudOrder>,
+    query: Query<(Entity, &Name), (Added<PropertyValue>, Without<HudElement>)>,
+) {
+    for (entity, name) in query.iter() {
+        if physics_config::PHYSICS_PROPERTIES.contains(&name.as_str()) {
+            commands
+                .entity(entity)
+                .insert(HudElement::EditThis)
+                .insert(hud_order.next().in_group("2. Physics"));
+        }
+    }
 }
 
 #[derive(Component)]
@@ -75,6 +125,8 @@ pub enum HudElement {
     TextWithSource(HudSrc),
     ToggleButtonProperty(String, String, String),
     EditThis,
+    // pause / step / slow motion of the simulation
+    SimTimeControls,
 }
 
 #[derive(Component)]
@@ -107,6 +159,7 @@ pub fn hud_egui_system(
     hud_elements_query: Query<(Entity, &HudOrder, &HudElement)>,
     mut string_edit_query: Query<&mut StringEdit>,
     mut hud_plot_diagnostic: Query<&mut HudPlotDiagnostic>,
+    mut sim_time: Option<ResMut<SimTime>>,
 ) {
     let mut ordered: Vec<_> = hud_elements_query.iter().collect();
     ordered.sort_by_key(|(_, o, _)| *o);
@@ -169,6 +222,37 @@ pub fn hud_egui_system(
                             }
                         }
                     }
+                    HudElement::SimTimeControls => match sim_time.as_mut() {
+                        Some(sim_time) => {
+                            ui.label(format!(
+                                "time scale: {:.3}{}",
+                                sim_time.scale,
+                                if sim_time.paused { " (paused)" } else { "" }
+                            ));
+                            if sim_time.is_locked() {
+                                ui.label("time controls disabled in networked games");
+                            } else {
+                                ui.horizontal(|ui| {
+                                    let pause = if sim_time.paused { "resume" } else { "pause" };
+                                    if ui.button(pause).clicked() {
+                                        sim_time.toggle_pause();
+                                    }
+                                    if ui.button("step").clicked() {
+                                        sim_time.request_step();
+                                    }
+                                    if ui.button("slower").clicked() {
+                                        sim_time.slower();
+                                    }
+                                    if ui.button("faster").clicked() {
+                                        sim_time.faster();
+                                    }
+                                });
+                            }
+                        }
+                        None => {
+                            ui.label("no sim time");
+                        }
+                    },
                     HudElement::EditThis => match property_query.get(entity) {
                         Ok((property_value, property_name)) => {
                             match property_value {
@@ -313,6 +397,7 @@ impl Plugin for DebugHudPlugin {
         app.init_resource::<HudOrder>()
             .add_startup_system(hud_egui_setup_system)
             .add_system(hud_egui_system)
+            .add_system(hud_edit_properties_system)
             // .add_system(hud_egui_plot_system.system())
             ;
     }
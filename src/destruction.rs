@@ -0,0 +1,293 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    auto_collider::{AttachCollider, AutoCollider},
+    fx::{
+        scorch::Scorch,
+        tween::{Ease, TranslationLens, Tween},
+    },
+    game::{spawn_tile, GameTime, GlobalState, Player, RemoteAuthority, Tile},
+    hex::HexCube,
+};
+
+// destroyed tiles are despawned once they fell below this height
+pub const TILE_KILL_HEIGHT: f32 = -10.0;
+
+// approximate shape of a loose tile, the mesh collider is a trimesh, which has no mass
+const TILE_HALF_HEIGHT: f32 = 0.05;
+const TILE_RADIUS: f32 = 0.5;
+
+// regrown tiles rise from this far below their original height
+const RISE_DEPTH: f32 = 1.0;
+
+pub struct TileRegrowSettings {
+    pub enabled: bool,
+    // seconds (of GameTime) between a tile being knocked loose and regrowing
+    pub delay: f32,
+    pub rise_duration: f32,
+}
+
+impl Default for TileRegrowSettings {
+    fn default() -> Self {
+        TileRegrowSettings {
+            enabled: true,
+            delay: 10.0,
+            rise_duration: 1.5,
+        }
+    }
+}
+
+// knocks the tile at cube loose, with an impulse applied to the now dynamic body
+#[derive(Clone, Debug)]
+pub struct DestroyTile {
+    pub cube: HexCube,
+    pub impulse: Vec3,
+}
+
+// A tile that was knocked loose and falls off the board. Without the Tile component the cell is a
+// hole, which blocks moves and line of sight.
+#[derive(Component, Clone, Debug)]
+pub struct DestroyedTile {
+    pub cube: HexCube,
+    pub height: f32,
+    // unscorched material, used for regrowing the tile
+    pub material: Handle<StandardMaterial>,
+}
+
+// pending regrow of a destroyed tile
+#[derive(Component, Clone, Debug)]
+pub struct TileRegrow {
+    pub cube: HexCube,
+    pub height: f32,
+    pub material: Handle<StandardMaterial>,
+    pub time_left: f32,
+}
+
+// a regrown tile on its way up into place
+#[derive(Component, Clone, Copy, Debug)]
+#[component(storage = "SparseSet")]
+pub struct RisingTile;
+
+#[allow(clippy::type_complexity)]
+pub fn destroy_tile_system(
+    mut commands: Commands,
+    mut events: EventReader<DestroyTile>,
+    settings: Res<TileRegrowSettings>,
+    query: Query<
+        (
+            Entity,
+            &HexCube,
+            &Transform,
+            &Handle<StandardMaterial>,
+            Option<&Scorch>,
+        ),
+        With<Tile>,
+    >,
+) {
+    for event in events.iter() {
+        for (entity, cube, transform, material, scorch) in query.iter() {
+            if *cube != event.cube {
+                continue;
+            }
+            let material = scorch
+                .and_then(Scorch::base_material)
+                .unwrap_or(material)
+                .clone();
+            commands
+                .entity(entity)
+                .remove::<Tile>()
+                .remove::<AttachCollider>()
+                .remove::<AutoCollider>()
+                .insert(DestroyedTile {
+                    cube: *cube,
+                    height: transform.translation.y,
+                    material: material.clone(),
+                })
+                .insert(RigidBody::Dynamic)
+                .insert(Collider::cylinder(TILE_HALF_HEIGHT, TILE_RADIUS))
+                .insert(ExternalImpulse {
+                    impulse: event.impulse,
+                    torque_impulse: Vec3::X * event.impulse.length() * 0.01,
+                });
+
+            // counted from here rather than from when the loose tile has fallen, which depends on
+            // the (not deterministic) physics
+            if settings.enabled {
+                commands
+                    .spawn()
+                    .insert(TileRegrow {
+                        cube: *cube,
+                        height: transform.translation.y,
+                        material,
+                        time_left: settings.delay,
+                    })
+                    .insert(Name::new("tile_regrow"));
+            }
+        }
+    }
+}
+
+pub fn destroyed_tile_fall_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform), With<DestroyedTile>>,
+) {
+    for (entity, transform) in query.iter() {
+        if transform.translation.y < TILE_KILL_HEIGHT {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// Counts on GameTime, so that under lockstep holes refill in the same tick on every peer. The tile
+// is part of the board again right away, the rise is only for show.
+#[allow(clippy::too_many_arguments)]
+pub fn tile_regrow_system(
+    mut commands: Commands,
+    time: Res<GameTime>,
+    remote_authority: Option<Res<RemoteAuthority>>,
+    settings: Res<TileRegrowSettings>,
+    global_state: Res<GlobalState>,
+    mut query: Query<(Entity, &mut TileRegrow)>,
+    tile_query: Query<&HexCube, With<Tile>>,
+    player_query: Query<&HexCube, With<Player>>,
+    destroyed_query: Query<(Entity, &DestroyedTile)>,
+) {
+    // tiles are replicated from the server
+    if remote_authority.is_some() {
+        return;
+    }
+    for (entity, mut regrow) in query.iter_mut() {
+        regrow.time_left -= time.delta_seconds();
+        if regrow.time_left > 0.0 {
+            continue;
+        }
+        if player_query.iter().any(|cube| *cube == regrow.cube) {
+            // something fell into the hole, try again later
+            regrow.time_left = settings.delay;
+            continue;
+        }
+        commands.entity(entity).despawn();
+        if tile_query.iter().any(|cube| *cube == regrow.cube) {
+            continue;
+        }
+        // the loose tile may have come to rest somewhere instead of falling off the board
+        for (entity, destroyed) in destroyed_query.iter() {
+            if destroyed.cube == regrow.cube {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        let tile = spawn_tile(
+            &mut commands,
+            &global_state,
+            regrow.cube,
+            regrow.height,
+            regrow.material.clone(),
+        );
+        let end = regrow.cube.to_odd_r_screen().extend(regrow.height).xzy();
+        let rise = TranslationLens {
+            start: end - Vec3::Y * RISE_DEPTH,
+            end,
+        };
+        commands
+            .entity(tile)
+            .insert(RisingTile)
+            .insert(Transform::from_translation(rise.start))
+            .insert(Tween::new(Ease::CubicOut, settings.rise_duration, rise));
+    }
+}
+
+// the rise tween is removed once it completes
+pub fn rising_tile_system(
+    mut commands: Commands,
+    query: Query<Entity, (With<RisingTile>, Without<Tween<Transform>>)>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).remove::<RisingTile>();
+    }
+}
+
+pub struct DestructionPlugin;
+
+impl Plugin for DestructionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileRegrowSettings>()
+            .add_event::<DestroyTile>()
+            .add_system(destroy_tile_system)
+            .add_system(destroyed_tile_fall_system)
+            .add_system(tile_regrow_system)
+            .add_system(rising_tile_system);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::game_time_system, sim_time::SimTime};
+
+    // exact in binary, so that the countdown ends after a whole number of steps
+    const STEP: f32 = 0.125;
+    const REGROW_STEPS: usize = 4;
+
+    const CUBE: HexCube = HexCube { x: 1, y: -1, z: 0 };
+
+    // stands in for lockstep_system, which advances GameTime once per tick
+    fn fixed_step_system(mut game_time: ResMut<GameTime>) {
+        game_time.advance(STEP);
+    }
+
+    fn spawn_tile_system(mut commands: Commands, global_state: Res<GlobalState>) {
+        spawn_tile(&mut commands, &global_state, CUBE, 0.0, Handle::default());
+    }
+
+    fn tiles_at(app: &mut App, cube: HexCube) -> usize {
+        let mut query = app.world.query_filtered::<&HexCube, With<Tile>>();
+        query.iter(&app.world).filter(|c| **c == cube).count()
+    }
+
+    #[test]
+    fn destroyed_tile_regrows_after_fixed_steps() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<GlobalState>()
+            .insert_resource(GameTime::fixed_step())
+            .insert_resource(TileRegrowSettings {
+                enabled: true,
+                delay: STEP * REGROW_STEPS as f32,
+                rise_duration: 1.0,
+            })
+            .init_resource::<SimTime>()
+            .add_event::<DestroyTile>()
+            .add_startup_system(spawn_tile_system)
+            .add_system_to_stage(CoreStage::PreUpdate, game_time_system)
+            .add_system(destroy_tile_system)
+            .add_system(fixed_step_system.before(tile_regrow_system))
+            .add_system(tile_regrow_system)
+            .add_system(rising_tile_system);
+
+        app.update();
+        assert_eq!(tiles_at(&mut app, CUBE), 1);
+
+        app.world
+            .get_resource_mut::<Events<DestroyTile>>()
+            .unwrap()
+            .send(DestroyTile {
+                cube: CUBE,
+                impulse: Vec3::Y,
+            });
+        // the regrow countdown starts in the frame after the tile was destroyed
+        app.update();
+        assert_eq!(tiles_at(&mut app, CUBE), 0);
+        for _ in 1..REGROW_STEPS {
+            app.update();
+            assert_eq!(tiles_at(&mut app, CUBE), 0);
+        }
+        app.update();
+        assert_eq!(tiles_at(&mut app, CUBE), 1);
+
+        // the loose tile is replaced by the regrown one
+        let mut destroyed = app.world.query::<&DestroyedTile>();
+        assert_eq!(destroyed.iter(&app.world).count(), 0);
+    }
+}
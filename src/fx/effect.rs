@@ -0,0 +1,474 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadState, LoadedAsset},
+    ecs::system::SystemParam,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::hex::{world_to_cube, HexCube};
+
+use super::{
+    particles::{spawn_particle_emitter, EmitterSpawn},
+    pool::{ParticlePool, ParticleSpawn},
+    scorch::ScorchEvent,
+    shake::CameraShakeEvent,
+    FadeOut,
+};
+
+// number of distinct colors sampled from a gradient
+const GRADIENT_STEPS: u32 = 16;
+
+fn gradient_t(step: u32) -> f32 {
+    step as f32 / (GRADIENT_STEPS - 1) as f32
+}
+
+// effect presets live in assets/effects/<name>.effect (ron)
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "6b1a3f3e-3c4e-4d7b-9a55-1f0e2c8d7a41"]
+pub struct EffectDefinition {
+    pub emitter: EmitterShape,
+    pub mesh: ParticleMesh,
+    // particle colors are sampled at random from the gradient
+    pub color_gradient: Vec<ColorStop>,
+    pub velocity: VelocityDistribution,
+    pub lifetime: Lifetime,
+    // emitter origin relative to the spawn position
+    #[serde(default)]
+    pub offset: [f32; 3],
+    #[serde(default)]
+    pub simulation: ParticleSimulation,
+    // only used with ParticleSimulation::Physics
+    #[serde(default)]
+    pub restitution: f32,
+    // only used with ParticleSimulation::Physics, a light per particle defeats the purpose of the
+    // lightweight simulation.
+    #[serde(default)]
+    pub particle_light: Option<LightSettings>,
+    #[serde(default)]
+    pub flash_light: Option<FlashLight>,
+    #[serde(default)]
+    pub camera_shake: Option<CameraShakeSettings>,
+    #[serde(default)]
+    pub scorch: Option<ScorchSettings>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum EmitterShape {
+    // size^3 particles on a regular grid
+    Grid { size: u32, spacing: f32 },
+    Sphere { radius: f32, count: u32 },
+    Point { count: u32 },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum ParticleSimulation {
+    // one rapier rigid body per particle
+    Physics,
+    // simulated by particle_emitter_system and drawn as a single mesh, for large numbers of
+    // particles.
+    Lightweight {
+        gravity: f32,
+        drag: f32,
+        bounce: f32,
+    },
+}
+
+impl Default for ParticleSimulation {
+    fn default() -> Self {
+        ParticleSimulation::Physics
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum ParticleMesh {
+    Cube { size: f32 },
+    Icosphere { radius: f32 },
+}
+
+impl ParticleMesh {
+    fn radius(&self) -> f32 {
+        match self {
+            ParticleMesh::Cube { size } => size / 2.0,
+            ParticleMesh::Icosphere { radius } => *radius,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ColorStop {
+    pub t: f32,
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct VelocityDistribution {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Lifetime {
+    // time until the particles start to fade
+    pub delay: f32,
+    pub fade: f32,
+    // shrink the particles while they fade
+    #[serde(default)]
+    pub shrink: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LightSettings {
+    pub intensity: f32,
+    pub range: f32,
+    pub radius: f32,
+    // None: use the particle color
+    #[serde(default)]
+    pub color: Option<[f32; 4]>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FlashLight {
+    pub light: LightSettings,
+    pub offset: [f32; 3],
+    pub fade: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CameraShakeSettings {
+    pub trauma: f32,
+    #[serde(default)]
+    pub impulse: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ScorchSettings {
+    // in hex cells
+    pub radius: i32,
+    pub intensity: f32,
+}
+
+impl EffectDefinition {
+    pub fn sample_color(&self, t: f32) -> Color {
+        let stops = &self.color_gradient;
+        let to_color = |c: [f32; 4]| Color::rgba(c[0], c[1], c[2], c[3]);
+        match stops.iter().position(|stop| stop.t >= t) {
+            None => stops
+                .last()
+                .map_or(Color::WHITE, |stop| to_color(stop.color)),
+            Some(0) => to_color(stops[0].color),
+            Some(i) => {
+                let (a, b) = (&stops[i - 1], &stops[i]);
+                let f = ((t - a.t) / (b.t - a.t).max(f32::EPSILON)).clamp(0.0, 1.0);
+                let a = Vec4::from(a.color);
+                let b = Vec4::from(b.color);
+                to_color((a + (b - a) * f).into())
+            }
+        }
+    }
+
+    // particle offsets relative to the effect origin
+    pub fn emitter_positions(&self, rng: &mut impl Rng) -> Vec<Vec3> {
+        match self.emitter {
+            EmitterShape::Grid { size, spacing } => {
+                let mut positions = Vec::new();
+                for z in 0..size {
+                    for y in 0..size {
+                        for x in 0..size {
+                            positions.push(Vec3::new(x as f32, y as f32, z as f32) * spacing);
+                        }
+                    }
+                }
+                positions
+            }
+            EmitterShape::Sphere { radius, count } => (0..count)
+                .map(|_| {
+                    let dir = Vec3::new(
+                        rng.gen_range(-1.0..1.0),
+                        rng.gen_range(-1.0..1.0),
+                        rng.gen_range(-1.0..1.0),
+                    )
+                    .normalize_or_zero();
+                    dir * radius * rng.gen_range(0.0f32..1.0).cbrt()
+                })
+                .collect(),
+            EmitterShape::Point { count } => vec![Vec3::ZERO; count as usize],
+        }
+    }
+}
+
+impl LightSettings {
+    fn point_light(&self, particle_color: Color) -> PointLight {
+        PointLight {
+            intensity: self.intensity,
+            range: self.range,
+            radius: self.radius,
+            color: self
+                .color
+                .map_or(particle_color, |c| Color::rgba(c[0], c[1], c[2], c[3])),
+            ..default()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct EffectDefinitionLoader;
+
+impl AssetLoader for EffectDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let definition: EffectDefinition = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(definition));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["effect"]
+    }
+}
+
+#[derive(Default)]
+pub struct EffectLibrary {
+    definitions: HashMap<String, Handle<EffectDefinition>>,
+    meshes: HashMap<String, Handle<Mesh>>,
+}
+
+impl EffectLibrary {
+    pub fn load(&mut self, name: &str, asset_server: &AssetServer) -> Handle<EffectDefinition> {
+        self.definitions
+            .entry(name.to_string())
+            .or_insert_with(|| asset_server.load(&format!("effects/{}.effect", name)))
+            .clone()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SpawnEffect {
+    pub name: String,
+    pub pos: Vec3,
+    // cell the effect belongs to (e.g. the center of an explosion), otherwise derived from pos
+    pub cube: Option<HexCube>,
+}
+
+#[derive(SystemParam)]
+pub struct EffectSpawner<'w, 's> {
+    events: EventWriter<'w, 's, SpawnEffect>,
+}
+
+impl<'w, 's> EffectSpawner<'w, 's> {
+    pub fn spawn_effect(&mut self, name: &str, pos: Vec3) {
+        self.events.send(SpawnEffect {
+            name: name.to_string(),
+            pos,
+            cube: None,
+        });
+    }
+
+    pub fn spawn_effect_on_cell(&mut self, name: &str, pos: Vec3, cube: HexCube) {
+        self.events.send(SpawnEffect {
+            name: name.to_string(),
+            pos,
+            cube: Some(cube),
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_effect_instance(
+    commands: &mut Commands,
+    definition: &EffectDefinition,
+    mesh: Handle<Mesh>,
+    pos: Vec3,
+    pool: &mut ParticlePool,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let mut rng = rand::thread_rng();
+    let radius = definition.mesh.radius();
+    let min = Vec3::from(definition.velocity.min);
+    let max = Vec3::from(definition.velocity.max);
+    let origin = pos + Vec3::from(definition.offset);
+
+    let sample_velocity = |rng: &mut ThreadRng| {
+        Vec3::new(
+            rng.gen_range(min.x..=max.x),
+            rng.gen_range(min.y..=max.y),
+            rng.gen_range(min.z..=max.z),
+        )
+    };
+
+    match definition.simulation {
+        ParticleSimulation::Physics => {
+            for offset in definition.emitter_positions(&mut rng) {
+                // quantized, so that the pool can share materials between particles
+                let color = definition.sample_color(gradient_t(rng.gen_range(0..GRADIENT_STEPS)));
+                let material = pool.material(color, materials);
+
+                pool.spawn(
+                    commands,
+                    ParticleSpawn {
+                        transform: Transform::from_translation(origin + offset),
+                        mesh: mesh.clone(),
+                        material,
+                        radius,
+                        restitution: definition.restitution,
+                        velocity: sample_velocity(&mut rng),
+                        fade_out: FadeOut {
+                            delay: definition.lifetime.delay,
+                            duration: definition.lifetime.fade,
+                            shrink: definition.lifetime.shrink,
+                            ..default()
+                        },
+                        light: definition
+                            .particle_light
+                            .as_ref()
+                            .map(|light| light.point_light(color)),
+                    },
+                );
+            }
+        }
+        ParticleSimulation::Lightweight {
+            gravity,
+            drag,
+            bounce,
+        } => {
+            // A single mesh can only have one material, so the particles are split into one
+            // emitter per gradient color, sampled the same way as for physics particles.
+            let mut groups: HashMap<u32, Vec<(Vec3, Vec3)>> = HashMap::default();
+            for offset in definition.emitter_positions(&mut rng) {
+                groups
+                    .entry(rng.gen_range(0..GRADIENT_STEPS))
+                    .or_default()
+                    .push((origin + offset, sample_velocity(&mut rng)));
+            }
+            // copied, the emitter mesh is added to the same assets
+            let template = match meshes.get(&mesh) {
+                Some(template) => template.clone(),
+                None => return,
+            };
+            for (step, particles) in groups {
+                let material = pool.material(definition.sample_color(gradient_t(step)), materials);
+                let emitter = EmitterSpawn {
+                    template: &template,
+                    material,
+                    particles,
+                    radius,
+                    gravity,
+                    drag,
+                    bounce,
+                    delay: definition.lifetime.delay,
+                    fade: definition.lifetime.fade,
+                };
+                if spawn_particle_emitter(commands, meshes, emitter).is_none() {
+                    warn!("unsupported particle mesh for lightweight simulation");
+                    break;
+                }
+            }
+        }
+    }
+
+    if let Some(flash) = &definition.flash_light {
+        let point_light = flash.light.point_light(definition.sample_color(0.0));
+        commands
+            .spawn_bundle(PointLightBundle {
+                point_light,
+                ..default()
+            })
+            .insert(Transform::from_translation(pos + Vec3::from(flash.offset)))
+            .insert(FadeOut::new(0.0, flash.fade));
+    }
+}
+
+// effects requested before their definition finished loading are kept until it is available
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_effect_system(
+    mut commands: Commands,
+    mut events: EventReader<SpawnEffect>,
+    mut pending: Local<Vec<SpawnEffect>>,
+    asset_server: Res<AssetServer>,
+    mut library: ResMut<EffectLibrary>,
+    mut pool: ResMut<ParticlePool>,
+    definitions: Res<Assets<EffectDefinition>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut shake_events: EventWriter<CameraShakeEvent>,
+    mut scorch_events: EventWriter<ScorchEvent>,
+) {
+    pending.extend(events.iter().cloned());
+
+    let mut still_pending = Vec::new();
+    for effect in pending.drain(..) {
+        let handle = library.load(&effect.name, &asset_server);
+        match definitions.get(&handle) {
+            Some(definition) => {
+                let mesh = library
+                    .meshes
+                    .entry(effect.name.clone())
+                    .or_insert_with(|| match definition.mesh {
+                        ParticleMesh::Cube { size } => meshes.add(shape::Cube { size }.into()),
+                        ParticleMesh::Icosphere { radius } => meshes.add(
+                            shape::Icosphere {
+                                radius,
+                                subdivisions: 1,
+                            }
+                            .into(),
+                        ),
+                    })
+                    .clone();
+                info!("spawn effect {} at {:?}", effect.name, effect.pos);
+                if let Some(shake) = &definition.camera_shake {
+                    shake_events.send(CameraShakeEvent {
+                        origin: Some(effect.pos),
+                        trauma: shake.trauma,
+                        impulse: shake.impulse,
+                    });
+                }
+                if let Some(scorch) = &definition.scorch {
+                    scorch_events.send(ScorchEvent {
+                        center: effect.cube.unwrap_or_else(|| world_to_cube(effect.pos)),
+                        radius: scorch.radius,
+                        intensity: scorch.intensity,
+                    });
+                }
+                spawn_effect_instance(
+                    &mut commands,
+                    definition,
+                    mesh,
+                    effect.pos,
+                    &mut pool,
+                    &mut meshes,
+                    &mut materials,
+                );
+            }
+            None if asset_server.get_load_state(&handle) == LoadState::Failed => {
+                error!("failed to load effect definition: {}", effect.name);
+            }
+            None => still_pending.push(effect),
+        }
+    }
+    *pending = still_pending;
+}
+
+// reloaded definitions may use a different mesh
+pub fn effect_definition_changed_system(
+    mut events: EventReader<AssetEvent<EffectDefinition>>,
+    mut library: ResMut<EffectLibrary>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            let library = &mut *library;
+            for (name, definition) in library.definitions.iter() {
+                if definition == handle {
+                    library.meshes.remove(name);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+
+use super::{
+    tween::{Ease, RotateAxisLens, Tween, TweenCompleted},
+    FLIP_COMPLETED, FLIP_HALFWAY,
+};
+use crate::hex::{HexCube, HEX_CUBE_DIRECTIONS};
+
+pub const FLIP_DURATION: f32 = 1.0;
+
+// Flips a tile over toward one of its neighbors (index into HEX_CUBE_DIRECTIONS). The material is
+// swapped when the tile stands on its edge, TileFlipped is sent once it lies flat again.
+#[derive(Component, Clone, Default)]
+#[component(storage = "SparseSet")]
+pub struct FlipTile {
+    pub direction: usize,
+    pub material: Option<Handle<StandardMaterial>>,
+}
+
+impl FlipTile {
+    pub fn new(direction: usize) -> Self {
+        FlipTile {
+            direction: direction % HEX_CUBE_DIRECTIONS.len(),
+            material: None,
+        }
+    }
+
+    pub fn with_material(mut self, material: Handle<StandardMaterial>) -> Self {
+        self.material = Some(material);
+        self
+    }
+
+    // horizontal axis perpendicular to the direction of the flip
+    fn axis(&self, cube: HexCube) -> Vec3 {
+        let neighbor = cube + HEX_CUBE_DIRECTIONS[self.direction % HEX_CUBE_DIRECTIONS.len()];
+        let dir = neighbor.to_odd_r_screen() - cube.to_odd_r_screen();
+        Vec3::Y
+            .cross(Vec3::new(dir.x, 0.0, dir.y))
+            .normalize_or_zero()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TileFlipped {
+    pub cube: HexCube,
+}
+
+pub fn flip_tile_system(
+    mut commands: Commands,
+    query: Query<(Entity, &HexCube, &FlipTile), Added<FlipTile>>,
+) {
+    for (entity, cube, flip) in query.iter() {
+        let first_half = RotateAxisLens {
+            axis: flip.axis(*cube),
+            start: 0.0,
+            end: std::f32::consts::FRAC_PI_2,
+        };
+        commands.entity(entity).insert(
+            Tween::new(Ease::QuadIn, FLIP_DURATION / 2.0, first_half)
+                .with_completed_event(FLIP_HALFWAY),
+        );
+    }
+}
+
+pub fn flip_tile_completed_system(
+    mut commands: Commands,
+    mut events: EventReader<TweenCompleted>,
+    mut flipped_events: EventWriter<TileFlipped>,
+    query: Query<(&HexCube, &FlipTile)>,
+) {
+    for event in events.iter() {
+        let (cube, flip) = match query.get(event.entity) {
+            Ok(tile) => tile,
+            Err(_) => continue,
+        };
+        match event.user_data {
+            FLIP_HALFWAY => {
+                // a hex tile is symmetric under the half turn, so the second half can continue from
+                // the opposite side and end at the original orientation.
+                let second_half = RotateAxisLens {
+                    axis: flip.axis(*cube),
+                    start: -std::f32::consts::FRAC_PI_2,
+                    end: 0.0,
+                };
+                let mut ec = commands.entity(event.entity);
+                ec.insert(
+                    Tween::new(Ease::QuadOut, FLIP_DURATION / 2.0, second_half)
+                        .with_completed_event(FLIP_COMPLETED),
+                );
+                if let Some(material) = &flip.material {
+                    ec.insert(material.clone());
+                }
+            }
+            FLIP_COMPLETED => {
+                commands.entity(event.entity).remove::<FlipTile>();
+                flipped_events.send(TileFlipped { cube: *cube });
+            }
+            _ => (),
+        }
+    }
+}
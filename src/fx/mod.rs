@@ -0,0 +1,304 @@
+use std::sync::Arc;
+
+use bevy::{asset::HandleId, prelude::*, utils::HashMap};
+
+use crate::{
+    combat::ExplosionEvent,
+    game::{GameSystem, GameTime},
+    hex::HexCube,
+    sim_time::SimTime,
+};
+
+use self::effect::{
+    effect_definition_changed_system, spawn_effect_system, EffectDefinition,
+    EffectDefinitionLoader, EffectLibrary, EffectSpawner, SpawnEffect,
+};
+use self::flip::{flip_tile_completed_system, flip_tile_system, TileFlipped};
+use self::particles::particle_emitter_system;
+use self::pool::{
+    particle_kill_system, particle_restitution_system, pool_recycle_system,
+    setup_pool_diagnostics_system, switch_off_light, ParticlePool, PooledLight, PooledParticle,
+};
+use self::scorch::{
+    scorch_base_changed_system, scorch_event_system, scorch_material_system, ScorchEvent,
+    ScorchMaterials,
+};
+use self::shake::{
+    camera_shake_event_system, camera_shake_system, setup_camera_shake_property_system,
+    CameraShakeEvent,
+};
+use self::tween::{
+    asset_tween_system, component_tween_system, AssetStepLens, Ease, Lens, MaterialFadeLens,
+    PointLightColorLens, ScaleJitterLens, ScaleLens, Tween, TweenCompleted,
+};
+
+pub mod effect;
+pub mod flip;
+pub mod particles;
+pub mod pool;
+pub mod scorch;
+pub mod shake;
+pub mod tween;
+
+// TweenCompleted user data of the tweens started by fx
+const FADE_OUT_COMPLETED: u64 = 1;
+const FLIP_HALFWAY: u64 = 2;
+const FLIP_COMPLETED: u64 = 3;
+
+// number of materials a fading material steps through
+const FADE_STEPS: usize = 16;
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FxSystem {
+    Tween,
+}
+
+// Fades the material of meshes (emissive to black, base color to transparent) or the color of
+// lights after a delay, then despawns them (or returns them to the particle pool). Meshes can
+// additionally shrink.
+#[derive(Component, Default, Clone)]
+pub struct FadeOut {
+    delay: f32,
+    duration: f32,
+    shrink: bool,
+    fading: bool,
+}
+
+impl FadeOut {
+    pub fn new(delay: f32, duration: f32) -> Self {
+        FadeOut {
+            delay,
+            duration,
+            ..default()
+        }
+    }
+
+    pub fn with_shrink(mut self) -> Self {
+        self.shrink = true;
+        self
+    }
+}
+
+// Materials may be shared (e.g. by the particle pool), so instead of modifying them, fading
+// entities step through copies with increasing fade. These are shared by all entities fading from
+// the same material.
+#[derive(Default)]
+pub struct FadeMaterials {
+    steps: HashMap<HandleId, Arc<[Handle<StandardMaterial>]>>,
+}
+
+impl FadeMaterials {
+    fn steps(
+        &mut self,
+        material: &Handle<StandardMaterial>,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Option<Arc<[Handle<StandardMaterial>]>> {
+        if let Some(steps) = self.steps.get(&material.id) {
+            return Some(steps.clone());
+        }
+        let base = materials.get(material)?.clone();
+        let mut fade = MaterialFadeLens {
+            emissive: base.emissive,
+            base_color: base.base_color,
+        };
+        let steps: Arc<[_]> = (0..FADE_STEPS)
+            .map(|i| {
+                let mut step = base.clone();
+                if step.alpha_mode == AlphaMode::Opaque {
+                    step.alpha_mode = AlphaMode::Blend;
+                }
+                fade.lerp(&mut step, i as f32 / (FADE_STEPS - 1) as f32);
+                materials.add(step)
+            })
+            .collect();
+        self.steps.insert(material.id, steps.clone());
+        Some(steps)
+    }
+}
+
+// the fade starts from the current material / light color, so nothing has to be known up front
+#[allow(clippy::type_complexity)]
+pub fn fade_out_system(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    mut query: Query<(
+        Entity,
+        &mut FadeOut,
+        Option<&PointLight>,
+        Option<&Handle<StandardMaterial>>,
+    )>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut fade_materials: ResMut<FadeMaterials>,
+) {
+    for (entity, mut fade_out, point_light, material) in query.iter_mut() {
+        if fade_out.fading {
+            continue;
+        }
+        if fade_out.delay > 0.0 {
+            fade_out.delay -= time.delta_seconds();
+            continue;
+        }
+        fade_out.fading = true;
+
+        let mut ec = commands.entity(entity);
+        let fade_steps =
+            material.and_then(|material| fade_materials.steps(material, &mut materials));
+        match (point_light, fade_steps) {
+            (Some(point_light), _) => {
+                let fade = PointLightColorLens {
+                    start: point_light.color,
+                    end: Color::BLACK,
+                };
+                ec.insert(
+                    Tween::new(Ease::Linear, fade_out.duration, fade)
+                        .with_completed_event(FADE_OUT_COMPLETED),
+                );
+            }
+            (None, Some(steps)) => {
+                let fade = AssetStepLens { steps };
+                ec.insert(
+                    Tween::new(Ease::Linear, fade_out.duration, fade)
+                        .with_completed_event(FADE_OUT_COMPLETED),
+                );
+                if fade_out.shrink {
+                    let shrink = ScaleLens {
+                        start: Vec3::ONE,
+                        end: Vec3::ZERO,
+                    };
+                    ec.insert(Tween::new(Ease::Linear, fade_out.duration, shrink));
+                }
+            }
+            (None, None) => {
+                let shrink = ScaleLens {
+                    start: Vec3::ONE,
+                    end: Vec3::ZERO,
+                };
+                ec.insert(
+                    Tween::new(Ease::Linear, fade_out.duration, shrink)
+                        .with_completed_event(FADE_OUT_COMPLETED),
+                );
+            }
+        }
+    }
+}
+
+pub fn fx_tween_completed_system(
+    mut commands: Commands,
+    mut events: EventReader<TweenCompleted>,
+    mut pool: ResMut<ParticlePool>,
+    fade_out_query: Query<(Option<&PooledParticle>, Option<&PooledLight>), With<FadeOut>>,
+) {
+    for event in events.iter() {
+        if event.user_data != FADE_OUT_COMPLETED {
+            continue;
+        }
+        match fade_out_query.get(event.entity) {
+            Ok((Some(particle), _)) => pool.release(&mut commands, event.entity, particle),
+            // released together with its particle
+            Ok((None, Some(_))) => switch_off_light(&mut commands, event.entity),
+            Ok((None, None)) => {
+                info!("exploding: fadeout despawn {:?}", event.entity);
+                commands.entity(event.entity).despawn_recursive();
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct PlayerExplosion {
+    pub time_left: f32,
+}
+
+pub fn player_explosion_system(
+    mut commands: Commands,
+    time: Res<GameTime>,
+    started_query: Query<(Entity, &PlayerExplosion), Added<PlayerExplosion>>,
+    mut query: Query<(Entity, &Transform, &mut PlayerExplosion, Option<&HexCube>)>,
+    mut effects: EffectSpawner,
+    mut explosion_events: EventWriter<ExplosionEvent>,
+) {
+    // explosions restored from a savegame or snapshot may already be under way
+    for (entity, explosion) in started_query.iter() {
+        let jitter = ScaleJitterLens {
+            start_amplitude: (1.0 - explosion.time_left).clamp(0.0, 1.0) * 0.3,
+            end_amplitude: 0.3,
+            min: 0.2,
+            max: 1.4,
+        };
+        commands
+            .entity(entity)
+            .insert(Tween::new(Ease::Linear, explosion.time_left, jitter));
+    }
+
+    for (entity, transform, mut explosion, cube) in query.iter_mut() {
+        explosion.time_left -= time.delta_seconds();
+
+        if explosion.time_left <= 0.0 {
+            commands.entity(entity).despawn_recursive();
+            match cube {
+                Some(cube) => {
+                    effects.spawn_effect_on_cell("explosion", transform.translation, *cube)
+                }
+                None => effects.spawn_effect("explosion", transform.translation),
+            }
+            if let Some(cube) = cube {
+                explosion_events.send(ExplosionEvent {
+                    center: *cube,
+                    pos: transform.translation,
+                });
+            }
+        }
+    }
+}
+
+// load commonly used effects up front, so that the first explosion is not delayed
+fn preload_effects_system(asset_server: Res<AssetServer>, mut library: ResMut<EffectLibrary>) {
+    library.load("explosion", &asset_server);
+}
+
+pub struct FxPlugin;
+
+impl Plugin for FxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<EffectDefinition>()
+            .init_asset_loader::<EffectDefinitionLoader>()
+            .init_resource::<EffectLibrary>()
+            .init_resource::<ParticlePool>()
+            .add_event::<SpawnEffect>()
+            .add_event::<TweenCompleted>()
+            .add_event::<TileFlipped>()
+            .add_event::<CameraShakeEvent>()
+            .add_event::<ScorchEvent>()
+            .init_resource::<ScorchMaterials>()
+            .init_resource::<FadeMaterials>()
+            .add_startup_system(preload_effects_system)
+            .add_startup_system(setup_pool_diagnostics_system)
+            .add_startup_system(setup_camera_shake_property_system)
+            .add_system_to_stage(CoreStage::First, pool_recycle_system)
+            .add_system(player_explosion_system.after(GameSystem::ApplyCommands))
+            .add_system(spawn_effect_system.after(player_explosion_system))
+            .add_system(effect_definition_changed_system)
+            .add_system(particle_emitter_system)
+            .add_system(camera_shake_event_system.after(spawn_effect_system))
+            .add_system(camera_shake_system.after(camera_shake_event_system))
+            .add_system(scorch_event_system.after(spawn_effect_system))
+            .add_system(scorch_material_system.after(scorch_event_system))
+            .add_system(scorch_base_changed_system)
+            .add_system(fade_out_system)
+            .add_system(particle_kill_system)
+            .add_system(particle_restitution_system)
+            .add_system(flip_tile_system.before(FxSystem::Tween))
+            .add_system_set(
+                SystemSet::new()
+                    .label(FxSystem::Tween)
+                    .with_system(component_tween_system::<Transform>)
+                    .with_system(component_tween_system::<PointLight>)
+                    .with_system(component_tween_system::<Handle<StandardMaterial>>)
+                    .with_system(asset_tween_system::<StandardMaterial>),
+            )
+            .add_system(fx_tween_completed_system.after(FxSystem::Tween))
+            .add_system(flip_tile_completed_system.after(FxSystem::Tween));
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+        view::NoFrustumCulling,
+    },
+    utils::HashMap,
+};
+
+use crate::{
+    game::Tile,
+    hex::{world_to_cube, HexCube},
+    sim_time::SimTime,
+};
+
+// approximate height of the tile surface above the tile origin
+const TILE_SURFACE_OFFSET: f32 = 0.1;
+
+// particles below this are dropped, e.g. after falling off the board
+const KILL_HEIGHT: f32 = -10.0;
+
+struct SimParticle {
+    pos: Vec3,
+    vel: Vec3,
+}
+
+// vertex data of a single particle, instanced into the emitter mesh
+struct ParticleTemplate {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl ParticleTemplate {
+    fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+            VertexAttributeValues::Float32x3(v) => v.clone(),
+            _ => return None,
+        };
+        let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL)? {
+            VertexAttributeValues::Float32x3(v) => v.clone(),
+            _ => return None,
+        };
+        let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0)? {
+            VertexAttributeValues::Float32x2(v) => v.clone(),
+            _ => return None,
+        };
+        let indices = match mesh.indices()? {
+            Indices::U16(i) => i.iter().map(|i| *i as u32).collect(),
+            Indices::U32(i) => i.clone(),
+        };
+        Some(ParticleTemplate {
+            positions,
+            normals,
+            uvs,
+            indices,
+        })
+    }
+}
+
+// A whole effect simulated without rapier: gravity, drag and bouncing off the board. All
+// particles are rendered through a single mesh, which is rebuilt every frame.
+#[derive(Component)]
+pub struct ParticleEmitter {
+    particles: Vec<SimParticle>,
+    template: ParticleTemplate,
+    radius: f32,
+    gravity: f32,
+    drag: f32,
+    bounce: f32,
+    until_start: f32,
+    left: f32,
+    start: f32,
+}
+
+pub struct EmitterSpawn<'a> {
+    pub template: &'a Mesh,
+    pub material: Handle<StandardMaterial>,
+    pub particles: Vec<(Vec3, Vec3)>,
+    pub radius: f32,
+    pub gravity: f32,
+    pub drag: f32,
+    pub bounce: f32,
+    pub delay: f32,
+    pub fade: f32,
+}
+
+pub fn spawn_particle_emitter(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    emitter: EmitterSpawn,
+) -> Option<Entity> {
+    let template = ParticleTemplate::from_mesh(emitter.template)?;
+    let particle_emitter = ParticleEmitter {
+        particles: emitter
+            .particles
+            .into_iter()
+            .map(|(pos, vel)| SimParticle { pos, vel })
+            .collect(),
+        template,
+        radius: emitter.radius,
+        gravity: emitter.gravity,
+        drag: emitter.drag,
+        bounce: emitter.bounce,
+        until_start: emitter.delay,
+        left: emitter.fade,
+        start: emitter.fade,
+    };
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    particle_emitter.build_mesh(&mut mesh, 1.0);
+
+    let entity = commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(mesh),
+            material: emitter.material,
+            ..default()
+        })
+        // the mesh changes every frame, its initial bounds are meaningless
+        .insert(NoFrustumCulling)
+        .insert(particle_emitter)
+        .id();
+    Some(entity)
+}
+
+impl ParticleEmitter {
+    fn step(&mut self, dt: f32, ground: &HashMap<HexCube, f32>) {
+        let radius = self.radius;
+        for particle in self.particles.iter_mut() {
+            particle.vel.y -= self.gravity * dt;
+            particle.vel *= (1.0 - self.drag * dt).max(0.0);
+            let prev = particle.pos;
+            particle.pos += particle.vel * dt;
+
+            if let Some(ground) = ground.get(&world_to_cube(particle.pos)) {
+                let ground = ground + TILE_SURFACE_OFFSET + radius;
+                // only bounce when coming from above, particles next to a tile fall past it
+                if particle.pos.y < ground && prev.y >= ground - radius && particle.vel.y < 0.0 {
+                    particle.pos.y = ground;
+                    particle.vel.y = -particle.vel.y * self.bounce;
+                }
+            }
+        }
+        self.particles
+            .retain(|particle| particle.pos.y > KILL_HEIGHT);
+    }
+
+    fn build_mesh(&self, mesh: &mut Mesh, scale: f32) {
+        let template = &self.template;
+        let n = self.particles.len();
+        let mut positions = Vec::with_capacity(n * template.positions.len());
+        let mut normals = Vec::with_capacity(n * template.normals.len());
+        let mut uvs = Vec::with_capacity(n * template.uvs.len());
+        let mut indices = Vec::with_capacity(n * template.indices.len());
+
+        for particle in self.particles.iter() {
+            let base = positions.len() as u32;
+            positions.extend(
+                template
+                    .positions
+                    .iter()
+                    .map(|p| (Vec3::from(*p) * scale + particle.pos).to_array()),
+            );
+            normals.extend_from_slice(&template.normals);
+            uvs.extend_from_slice(&template.uvs);
+            indices.extend(template.indices.iter().map(|i| i + base));
+        }
+
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+    }
+}
+
+pub fn particle_emitter_system(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tile_query: Query<(&HexCube, &GlobalTransform), With<Tile>>,
+    mut query: Query<(Entity, &mut ParticleEmitter, &Handle<Mesh>)>,
+) {
+    if query.is_empty() {
+        return;
+    }
+    let dt = time.delta_seconds();
+    let ground: HashMap<HexCube, f32> = tile_query
+        .iter()
+        .map(|(cube, transform)| (*cube, transform.translation.y))
+        .collect();
+
+    for (entity, mut emitter, mesh) in query.iter_mut() {
+        let scale = if emitter.until_start > 0.0 {
+            emitter.until_start -= dt;
+            1.0
+        } else {
+            let v = emitter.left / emitter.start;
+            emitter.left -= dt;
+            v
+        };
+
+        if emitter.left <= 0.0 || emitter.particles.is_empty() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        emitter.step(dt, &ground);
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            emitter.build_mesh(mesh, scale.clamp(0.0, 1.0));
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    prelude::*,
+    utils::HashMap,
+};
+use bevy_rapier3d::prelude::*;
+
+use super::{tween::Tween, FadeOut};
+
+// released particles beyond this are despawned instead of kept around for reuse
+pub const MAX_FREE_PARTICLES: usize = 1024;
+
+// particles that fell off the board are returned to the pool below this height
+pub const PARTICLE_KILL_HEIGHT: f32 = -10.0;
+
+pub const FX_PARTICLES_ACTIVE: DiagnosticId =
+    DiagnosticId::from_u128(0x6c0f2a3e_91d4_4f0b_8a7e_3b5d2c1e9f01);
+pub const FX_PARTICLES_FREE: DiagnosticId =
+    DiagnosticId::from_u128(0x6c0f2a3e_91d4_4f0b_8a7e_3b5d2c1e9f02);
+pub const FX_MATERIALS: DiagnosticId =
+    DiagnosticId::from_u128(0x6c0f2a3e_91d4_4f0b_8a7e_3b5d2c1e9f03);
+
+#[derive(Component)]
+pub struct PooledParticle {
+    light: Option<Entity>,
+    // shared material, replaced by the fade steps while fading
+    material: Handle<StandardMaterial>,
+    // of the effect definition, restored when the override is cleared
+    restitution: f32,
+}
+
+#[derive(Component)]
+pub struct PooledLight;
+
+pub struct ParticleSpawn {
+    pub transform: Transform,
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+    pub radius: f32,
+    pub restitution: f32,
+    pub velocity: Vec3,
+    pub fade_out: FadeOut,
+    pub light: Option<PointLight>,
+}
+
+// Recycles particle entities (including their light child) and shares one material per color.
+#[derive(Default)]
+pub struct ParticlePool {
+    free: Vec<(Entity, Option<Entity>)>,
+    // released particles only become reusable in the next frame, after the commands hiding them
+    // have been applied.
+    released: Vec<(Entity, Option<Entity>)>,
+    materials: HashMap<[u32; 4], Handle<StandardMaterial>>,
+    active: usize,
+    // replaces the restitution of the effect definitions, e.g. for tweaking in the hud. Changes also
+    // apply to particles already in flight (see particle_restitution_system).
+    pub restitution_override: Option<f32>,
+}
+
+impl ParticlePool {
+    pub fn num_active(&self) -> usize {
+        self.active
+    }
+
+    pub fn num_free(&self) -> usize {
+        self.free.len() + self.released.len()
+    }
+
+    pub fn num_materials(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn material(
+        &mut self,
+        color: Color,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        let key = color.as_rgba_f32().map(f32::to_bits);
+        self.materials
+            .entry(key)
+            .or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color: Color::BLACK,
+                    reflectance: 0.0,
+                    emissive: color,
+                    ..default()
+                })
+            })
+            .clone()
+    }
+
+    pub fn spawn(&mut self, commands: &mut Commands, particle: ParticleSpawn) -> Entity {
+        self.active += 1;
+        let (entity, mut light) = match self.free.pop() {
+            Some(pooled) => pooled,
+            None => (commands.spawn().id(), None),
+        };
+
+        let mut ec = commands.entity(entity);
+        ec.insert_bundle(PbrBundle {
+            transform: particle.transform,
+            mesh: particle.mesh,
+            material: particle.material.clone(),
+            ..default()
+        })
+        .insert(Collider::ball(particle.radius))
+        .insert(Restitution {
+            coefficient: self.restitution_override.unwrap_or(particle.restitution),
+            ..default()
+        })
+        .insert(RigidBody::Dynamic)
+        .insert(Velocity::linear(particle.velocity))
+        .insert(particle.fade_out.clone());
+
+        match (particle.light, light) {
+            (Some(point_light), Some(light)) => {
+                commands
+                    .entity(light)
+                    .insert(point_light)
+                    .insert(particle.fade_out);
+            }
+            (Some(point_light), None) => {
+                ec.with_children(|commands| {
+                    light = Some(
+                        commands
+                            .spawn_bundle(PointLightBundle {
+                                point_light,
+                                ..default()
+                            })
+                            .insert(particle.fade_out)
+                            .insert(PooledLight)
+                            .id(),
+                    );
+                });
+            }
+            // a light left over from a previous use stays switched off
+            (None, _) => (),
+        }
+        commands.entity(entity).insert(PooledParticle {
+            light,
+            material: particle.material,
+            restitution: particle.restitution,
+        });
+        entity
+    }
+
+    pub fn release(&mut self, commands: &mut Commands, entity: Entity, particle: &PooledParticle) {
+        // e.g. fell off the board in the same frame its fade out completed
+        if self
+            .released
+            .iter()
+            .any(|(released, _)| *released == entity)
+        {
+            return;
+        }
+        self.active = self.active.saturating_sub(1);
+        if self.num_free() >= MAX_FREE_PARTICLES {
+            commands.entity(entity).despawn_recursive();
+            return;
+        }
+        commands
+            .entity(entity)
+            .insert(Visibility { is_visible: false })
+            .insert(particle.material.clone())
+            .remove::<RigidBody>()
+            .remove::<Collider>()
+            .remove::<Velocity>()
+            .remove::<FadeOut>()
+            .remove::<Tween<Transform>>()
+            .remove::<Tween<Handle<StandardMaterial>>>();
+        if let Some(light) = particle.light {
+            switch_off_light(commands, light);
+        }
+        self.released.push((entity, particle.light));
+    }
+}
+
+pub fn switch_off_light(commands: &mut Commands, light: Entity) {
+    commands
+        .entity(light)
+        .insert(PointLight {
+            intensity: 0.0,
+            ..default()
+        })
+        .remove::<FadeOut>()
+        .remove::<Tween<PointLight>>();
+}
+
+pub fn particle_kill_system(
+    mut commands: Commands,
+    mut pool: ResMut<ParticlePool>,
+    query: Query<(Entity, &Transform, &PooledParticle), With<RigidBody>>,
+) {
+    for (entity, transform, particle) in query.iter() {
+        if transform.translation.y < PARTICLE_KILL_HEIGHT {
+            pool.release(&mut commands, entity, particle);
+        }
+    }
+}
+
+pub fn particle_restitution_system(
+    pool: Res<ParticlePool>,
+    mut applied_override: Local<Option<f32>>,
+    mut query: Query<(&PooledParticle, &mut Restitution), With<RigidBody>>,
+) {
+    if *applied_override == pool.restitution_override {
+        return;
+    }
+    *applied_override = pool.restitution_override;
+    for (particle, mut restitution) in query.iter_mut() {
+        restitution.coefficient = pool.restitution_override.unwrap_or(particle.restitution);
+    }
+}
+
+pub fn setup_pool_diagnostics_system(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(
+        FX_PARTICLES_ACTIVE,
+        "fx particles active",
+        20,
+    ));
+    diagnostics.add(Diagnostic::new(FX_PARTICLES_FREE, "fx particles free", 20));
+    diagnostics.add(Diagnostic::new(FX_MATERIALS, "fx materials", 20));
+}
+
+pub fn pool_recycle_system(mut pool: ResMut<ParticlePool>, mut diagnostics: ResMut<Diagnostics>) {
+    let pool = &mut *pool;
+    pool.free.append(&mut pool.released);
+
+    diagnostics.add_measurement(FX_PARTICLES_ACTIVE, pool.num_active() as f64);
+    diagnostics.add_measurement(FX_PARTICLES_FREE, pool.num_free() as f64);
+    diagnostics.add_measurement(FX_MATERIALS, pool.num_materials() as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::AssetPlugin;
+
+    use super::*;
+
+    const PARTICLES_PER_EXPLOSION: usize = 64;
+    const COLORS: [Color; 4] = [Color::RED, Color::GREEN, Color::BLUE, Color::YELLOW];
+
+    // an explosion every fourth frame, its particles fade out two frames later
+    fn explosion_system(
+        mut commands: Commands,
+        mut frame: Local<usize>,
+        mut pool: ResMut<ParticlePool>,
+        mut materials: ResMut<Assets<StandardMaterial>>,
+        query: Query<(Entity, &PooledParticle), With<RigidBody>>,
+    ) {
+        *frame += 1;
+        match *frame % 4 {
+            0 => {
+                for i in 0..PARTICLES_PER_EXPLOSION {
+                    let material = pool.material(COLORS[i % COLORS.len()], &mut materials);
+                    pool.spawn(
+                        &mut commands,
+                        ParticleSpawn {
+                            transform: Transform::default(),
+                            mesh: Handle::default(),
+                            material,
+                            radius: 0.05,
+                            restitution: 0.5,
+                            velocity: Vec3::Y,
+                            fade_out: FadeOut::new(0.0, 1.0),
+                            light: Some(PointLight::default()),
+                        },
+                    );
+                }
+            }
+            2 => {
+                for (entity, particle) in query.iter() {
+                    pool.release(&mut commands, entity, particle);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn bounded_growth_under_repeated_explosions() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<StandardMaterial>()
+            .add_asset::<Mesh>()
+            .init_resource::<Diagnostics>()
+            .init_resource::<ParticlePool>()
+            .add_startup_system(setup_pool_diagnostics_system)
+            .add_system_to_stage(CoreStage::First, pool_recycle_system)
+            .add_system(explosion_system);
+
+        let mut max_entities = 0;
+        for _ in 0..400 {
+            app.update();
+            max_entities = max_entities.max(app.world.entities().len() as usize);
+        }
+
+        // 100 explosions: the particles and lights of the first one are reused by all following
+        assert!(max_entities <= 2 * PARTICLES_PER_EXPLOSION);
+        let pool = app.world.get_resource::<ParticlePool>().unwrap();
+        assert_eq!(pool.num_active(), 0);
+        assert_eq!(pool.num_materials(), COLORS.len());
+        let materials = app
+            .world
+            .get_resource::<Assets<StandardMaterial>>()
+            .unwrap();
+        assert_eq!(materials.len(), COLORS.len());
+    }
+}
@@ -0,0 +1,179 @@
+use bevy::{asset::HandleId, prelude::*, utils::HashMap};
+
+use crate::{game::Tile, hex::HexCube};
+
+// number of distinct scorch materials per base material
+pub const SCORCH_LEVELS: u32 = 8;
+
+const SCORCH_COLOR: Color = Color::rgb(0.05, 0.04, 0.03);
+
+// Persistent burn marks on a tile, 0: untouched, 1: fully charred. The tile material is replaced by
+// a scorched variant of its base material.
+#[derive(Component, Clone, Default, Debug)]
+pub struct Scorch {
+    pub amount: f32,
+    base: Option<Handle<StandardMaterial>>,
+}
+
+impl Scorch {
+    pub fn new(amount: f32) -> Self {
+        Scorch {
+            amount: amount.clamp(0.0, 1.0),
+            base: None,
+        }
+    }
+
+    // the material the tile had before it was scorched
+    pub fn base_material(&self) -> Option<&Handle<StandardMaterial>> {
+        self.base.as_ref()
+    }
+
+    fn level(&self) -> u32 {
+        (self.amount.clamp(0.0, 1.0) * SCORCH_LEVELS as f32).round() as u32
+    }
+}
+
+// scorches tiles within radius of center, with the intensity falling off linearly with distance
+#[derive(Clone, Debug)]
+pub struct ScorchEvent {
+    pub center: HexCube,
+    pub radius: i32,
+    pub intensity: f32,
+}
+
+// scorched variants shared by all tiles with the same base material and scorch level
+#[derive(Default)]
+pub struct ScorchMaterials {
+    variants: HashMap<(HandleId, u32), Handle<StandardMaterial>>,
+    // variant -> base
+    bases: HashMap<HandleId, Handle<StandardMaterial>>,
+}
+
+fn scorched_material(base: &StandardMaterial, level: u32) -> StandardMaterial {
+    let f = level as f32 / SCORCH_LEVELS as f32 * 0.85;
+    let color = Vec4::from(base.base_color.as_rgba_f32());
+    let scorch = Vec4::from(SCORCH_COLOR.as_rgba_f32());
+    let c = color.lerp(scorch, f);
+    StandardMaterial {
+        base_color: Color::rgba(c.x, c.y, c.z, color.w),
+        perceptual_roughness: base.perceptual_roughness + (1.0 - base.perceptual_roughness) * f,
+        metallic: base.metallic * (1.0 - f),
+        ..base.clone()
+    }
+}
+
+impl ScorchMaterials {
+    fn variant(
+        &mut self,
+        base: &Handle<StandardMaterial>,
+        level: u32,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Option<Handle<StandardMaterial>> {
+        if level == 0 {
+            return Some(base.clone());
+        }
+        if let Some(variant) = self.variants.get(&(base.id, level)) {
+            return Some(variant.clone());
+        }
+        let material = scorched_material(materials.get(base)?, level);
+        let variant = materials.add(material);
+        self.variants.insert((base.id, level), variant.clone());
+        self.bases.insert(variant.id, base.clone());
+        Some(variant)
+    }
+}
+
+pub fn scorch_event_system(
+    mut commands: Commands,
+    mut events: EventReader<ScorchEvent>,
+    mut query: Query<(Entity, &HexCube, Option<&mut Scorch>), With<Tile>>,
+) {
+    let mut events = events.iter().peekable();
+    if events.peek().is_none() {
+        return;
+    }
+    let tiles: HashMap<HexCube, Entity> = query
+        .iter()
+        .map(|(entity, cube, _)| (*cube, entity))
+        .collect();
+
+    // summed up first, tiles without Scorch only get the component once the commands are applied
+    let mut amounts: HashMap<Entity, f32> = HashMap::default();
+    for event in events {
+        for cube in event.center.range(event.radius) {
+            let entity = match tiles.get(&cube) {
+                Some(entity) => *entity,
+                None => continue,
+            };
+            let falloff = 1.0 - cube.distance(&event.center) as f32 / (event.radius + 1) as f32;
+            *amounts.entry(entity).or_default() += event.intensity * falloff;
+        }
+    }
+
+    for (entity, amount) in amounts {
+        match query.get_mut(entity) {
+            Ok((_, _, Some(mut scorch))) => {
+                scorch.amount = (scorch.amount + amount).clamp(0.0, 1.0);
+            }
+            Ok((_, _, None)) => {
+                commands.entity(entity).insert(Scorch::new(amount));
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn scorch_material_system(
+    mut scorch_materials: ResMut<ScorchMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut query: Query<
+        (&mut Scorch, &mut Handle<StandardMaterial>),
+        Or<(Changed<Scorch>, Changed<Handle<StandardMaterial>>)>,
+    >,
+) {
+    for (mut scorch, mut material) in query.iter_mut() {
+        // anything but one of our variants (e.g. a material swapped in by a tile flip) is the new
+        // base material.
+        let base = match scorch_materials.bases.get(&material.id) {
+            Some(base) => base.clone(),
+            None => material.clone(),
+        };
+        if scorch.base.as_ref() != Some(&base) {
+            scorch.base = Some(base.clone());
+        }
+        let level = scorch.level();
+        if let Some(variant) = scorch_materials.variant(&base, level, &mut materials) {
+            if *material != variant {
+                *material = variant;
+            }
+        }
+    }
+}
+
+// keep the variants in sync with edits of their base material
+pub fn scorch_base_changed_system(
+    mut events: EventReader<AssetEvent<StandardMaterial>>,
+    scorch_materials: Res<ScorchMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if scorch_materials.bases.contains_key(&handle.id) {
+                // a variant itself
+                continue;
+            }
+            let base = match materials.get(handle) {
+                Some(base) => base.clone(),
+                None => continue,
+            };
+            for ((base_id, level), variant) in scorch_materials.variants.iter() {
+                if *base_id == handle.id {
+                    if let Some(material) = materials.get_mut(variant) {
+                        *material = scorched_material(&base, *level);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use bevy::{math::EulerRot, prelude::*};
+
+use crate::property::{property_f32, PropertyRegistry, PropertyValue};
+
+// global multiplier for all camera shake, 0 disables it
+pub const CAMERA_SHAKE_PROPERTY: &str = "fx.camera_shake";
+
+// distance at which the trauma of a shake event is halved
+pub const SHAKE_FALLOFF_DISTANCE: f32 = 3.0;
+
+// Trauma based camera shake: events add trauma, which decays linearly. The actual shake grows with
+// the square of the trauma, so small hits stay subtle.
+#[derive(Component)]
+pub struct CameraShake {
+    pub trauma: f32,
+    // trauma lost per second
+    pub decay: f32,
+    pub max_offset: f32,
+    // radians
+    pub max_angle: f32,
+    // displacement away from an explosion, springs back to zero
+    pub impulse: Vec3,
+    pub impulse_damping: f32,
+    applied_offset: Vec3,
+    applied_rotation: Quat,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        CameraShake {
+            trauma: 0.0,
+            decay: 1.0,
+            max_offset: 0.1,
+            max_angle: 0.05,
+            impulse: Vec3::ZERO,
+            impulse_damping: 8.0,
+            applied_offset: Vec3::ZERO,
+            applied_rotation: Quat::IDENTITY,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CameraShakeEvent {
+    // None: not attenuated by distance
+    pub origin: Option<Vec3>,
+    pub trauma: f32,
+    // camera displacement away from the origin, attenuated like the trauma
+    pub impulse: f32,
+}
+
+pub fn setup_camera_shake_property_system(mut commands: Commands) {
+    commands
+        .spawn()
+        .insert(Name::new(CAMERA_SHAKE_PROPERTY))
+        .insert(PropertyValue::String("1.0".into()));
+}
+
+pub fn camera_shake_event_system(
+    mut events: EventReader<CameraShakeEvent>,
+    mut query: Query<(&mut CameraShake, &Transform)>,
+) {
+    for event in events.iter() {
+        for (mut shake, transform) in query.iter_mut() {
+            // measured from the unshaken camera position
+            let camera_pos = transform.translation - shake.applied_offset;
+            let (falloff, away) = match event.origin {
+                Some(origin) => {
+                    let d = camera_pos - origin;
+                    let falloff = 1.0 / (1.0 + (d.length() / SHAKE_FALLOFF_DISTANCE).powi(2));
+                    (falloff, d.normalize_or_zero())
+                }
+                None => (1.0, Vec3::ZERO),
+            };
+            shake.trauma = (shake.trauma + event.trauma * falloff).min(1.0);
+            shake.impulse += away * event.impulse * falloff;
+        }
+    }
+}
+
+pub fn camera_shake_system(
+    time: Res<Time>,
+    property_registry: Res<PropertyRegistry>,
+    property_query: Query<&PropertyValue>,
+    mut query: Query<(&mut CameraShake, &mut Transform)>,
+) {
+    let intensity = property_f32(&property_registry, &property_query, CAMERA_SHAKE_PROPERTY)
+        .unwrap_or(1.0)
+        .max(0.0);
+    let dt = time.delta_seconds();
+    let t = time.seconds_since_startup() as f32;
+
+    for (mut shake, mut transform) in query.iter_mut() {
+        // undo last frame's shake, so that other systems can move the camera freely
+        transform.translation -= shake.applied_offset;
+        transform.rotation = transform.rotation * shake.applied_rotation.inverse();
+
+        shake.trauma = (shake.trauma - shake.decay * dt).max(0.0);
+        let damping = (1.0 - shake.impulse_damping * dt).max(0.0);
+        shake.impulse *= damping;
+
+        let amount = shake.trauma * shake.trauma * intensity;
+        // sums of sines as cheap smooth noise
+        let noise = |seed: f32| ((t * 23.0 + seed).sin() + (t * 37.0 + seed * 1.7).sin()) * 0.5;
+        let offset = Vec3::new(noise(0.0), noise(11.0), noise(23.0)) * shake.max_offset * amount
+            + shake.impulse * intensity;
+        let rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            noise(31.0) * shake.max_angle * amount,
+            noise(43.0) * shake.max_angle * amount,
+            noise(57.0) * shake.max_angle * amount,
+        );
+
+        transform.translation += offset;
+        transform.rotation = transform.rotation * rotation;
+        shake.applied_offset = offset;
+        shake.applied_rotation = rotation;
+    }
+}
@@ -0,0 +1,541 @@
+use std::sync::Arc;
+
+use bevy::{asset::Asset, prelude::*};
+use rand::prelude::*;
+
+use crate::sim_time::SimTime;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineInOut,
+}
+
+impl Ease {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Ease::SineInOut => -((std::f32::consts::PI * t).cos() - 1.0) / 2.0,
+        }
+    }
+}
+
+// maps the eased progress of a tween step onto (part of) the target
+pub trait Lens<T>: Send + Sync + 'static {
+    fn lerp(&mut self, target: &mut T, ratio: f32);
+}
+
+fn lerp_color(start: Color, end: Color, ratio: f32) -> Color {
+    let start = Vec4::from(start.as_rgba_f32());
+    let end = Vec4::from(end.as_rgba_f32());
+    let c = start.lerp(end, ratio);
+    Color::rgba(c.x, c.y, c.z, c.w)
+}
+
+pub struct TranslationLens {
+    pub start: Vec3,
+    pub end: Vec3,
+}
+
+impl Lens<Transform> for TranslationLens {
+    fn lerp(&mut self, target: &mut Transform, ratio: f32) {
+        target.translation = self.start.lerp(self.end, ratio);
+    }
+}
+
+pub struct RotationLens {
+    pub start: Quat,
+    pub end: Quat,
+}
+
+impl Lens<Transform> for RotationLens {
+    fn lerp(&mut self, target: &mut Transform, ratio: f32) {
+        target.rotation = self.start.slerp(self.end, ratio);
+    }
+}
+
+// unlike RotationLens this can turn by more than half a turn, and in a defined direction
+pub struct RotateAxisLens {
+    pub axis: Vec3,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Lens<Transform> for RotateAxisLens {
+    fn lerp(&mut self, target: &mut Transform, ratio: f32) {
+        let angle = self.start + (self.end - self.start) * ratio;
+        target.rotation = Quat::from_axis_angle(self.axis, angle);
+    }
+}
+
+pub struct ScaleLens {
+    pub start: Vec3,
+    pub end: Vec3,
+}
+
+impl Lens<Transform> for ScaleLens {
+    fn lerp(&mut self, target: &mut Transform, ratio: f32) {
+        target.scale = self.start.lerp(self.end, ratio);
+    }
+}
+
+// random walk of the scale, with a step size interpolated between start and end amplitude
+pub struct ScaleJitterLens {
+    pub start_amplitude: f32,
+    pub end_amplitude: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Lens<Transform> for ScaleJitterLens {
+    fn lerp(&mut self, target: &mut Transform, ratio: f32) {
+        let v = self.start_amplitude + (self.end_amplitude - self.start_amplitude) * ratio;
+        if v > 0.0 {
+            target.scale += Vec3::splat(rand::thread_rng().gen_range(-v..v));
+        }
+        target.scale = target
+            .scale
+            .clamp(Vec3::splat(self.min), Vec3::splat(self.max));
+    }
+}
+
+pub struct PointLightColorLens {
+    pub start: Color,
+    pub end: Color,
+}
+
+impl Lens<PointLight> for PointLightColorLens {
+    fn lerp(&mut self, target: &mut PointLight, ratio: f32) {
+        target.color = lerp_color(self.start, self.end, ratio);
+    }
+}
+
+pub struct PointLightIntensityLens {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Lens<PointLight> for PointLightIntensityLens {
+    fn lerp(&mut self, target: &mut PointLight, ratio: f32) {
+        target.intensity = self.start + (self.end - self.start) * ratio;
+    }
+}
+
+pub struct BaseColorLens {
+    pub start: Color,
+    pub end: Color,
+}
+
+impl Lens<StandardMaterial> for BaseColorLens {
+    fn lerp(&mut self, target: &mut StandardMaterial, ratio: f32) {
+        target.base_color = lerp_color(self.start, self.end, ratio);
+    }
+}
+
+pub struct EmissiveLens {
+    pub start: Color,
+    pub end: Color,
+}
+
+impl Lens<StandardMaterial> for EmissiveLens {
+    fn lerp(&mut self, target: &mut StandardMaterial, ratio: f32) {
+        target.emissive = lerp_color(self.start, self.end, ratio);
+    }
+}
+
+// fades emissive to black and base color to fully transparent, the material needs a blending
+// alpha mode for the latter.
+pub struct MaterialFadeLens {
+    pub emissive: Color,
+    pub base_color: Color,
+}
+
+impl Lens<StandardMaterial> for MaterialFadeLens {
+    fn lerp(&mut self, target: &mut StandardMaterial, ratio: f32) {
+        target.emissive = lerp_color(self.emissive, Color::BLACK, ratio);
+        let mut transparent = self.base_color;
+        transparent.set_a(0.0);
+        target.base_color = lerp_color(self.base_color, transparent, ratio);
+    }
+}
+
+// steps through a list of precomputed assets, e.g. shared fade materials
+pub struct AssetStepLens<T: Asset> {
+    pub steps: Arc<[Handle<T>]>,
+}
+
+impl<T: Asset> Lens<Handle<T>> for AssetStepLens<T> {
+    fn lerp(&mut self, target: &mut Handle<T>, ratio: f32) {
+        let last = self.steps.len().saturating_sub(1);
+        if let Some(step) = self.steps.get((ratio * last as f32).round() as usize) {
+            if *target != *step {
+                *target = step.clone();
+            }
+        }
+    }
+}
+
+struct TweenStep<T> {
+    duration: f32,
+    ease: Ease,
+    // None: pure delay
+    lens: Option<Box<dyn Lens<T>>>,
+}
+
+impl<T> TweenStep<T> {
+    fn apply(&mut self, target: &mut T, t: f32) {
+        if let Some(lens) = &mut self.lens {
+            lens.lerp(target, self.ease.apply(t));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TweenLoop {
+    Once,
+    Repeat(u32),
+    RepeatForever,
+    // alternates between running the steps forward and backward
+    PingPongForever,
+}
+
+pub struct TweenCompleted {
+    pub entity: Entity,
+    pub user_data: u64,
+}
+
+// A sequence of lens animations and delays on a component (or on the asset behind a handle, see
+// asset_tween_system). The component is removed once the tween completes.
+#[derive(Component)]
+pub struct Tween<T> {
+    steps: Vec<TweenStep<T>>,
+    current: usize,
+    elapsed: f32,
+    repeat: TweenLoop,
+    loops_done: u32,
+    backwards: bool,
+    completed_event: Option<u64>,
+}
+
+impl<T> Tween<T> {
+    pub fn new(ease: Ease, duration: f32, lens: impl Lens<T>) -> Self {
+        Tween {
+            steps: Vec::new(),
+            current: 0,
+            elapsed: 0.0,
+            repeat: TweenLoop::Once,
+            loops_done: 0,
+            backwards: false,
+            completed_event: None,
+        }
+        .then(ease, duration, lens)
+    }
+
+    pub fn then(mut self, ease: Ease, duration: f32, lens: impl Lens<T>) -> Self {
+        self.steps.push(TweenStep {
+            duration,
+            ease,
+            lens: Some(Box::new(lens)),
+        });
+        self
+    }
+
+    pub fn then_delay(mut self, duration: f32) -> Self {
+        self.steps.push(TweenStep {
+            duration,
+            ease: Ease::Linear,
+            lens: None,
+        });
+        self
+    }
+
+    // delay before the first step
+    pub fn with_delay(mut self, duration: f32) -> Self {
+        self.steps.insert(
+            0,
+            TweenStep {
+                duration,
+                ease: Ease::Linear,
+                lens: None,
+            },
+        );
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: TweenLoop) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    // send a TweenCompleted event with user_data once the tween completes
+    pub fn with_completed_event(mut self, user_data: u64) -> Self {
+        self.completed_event = Some(user_data);
+        self
+    }
+
+    fn total_duration(&self) -> f32 {
+        self.steps.iter().map(|step| step.duration).sum()
+    }
+
+    // returns true once the tween has completed
+    pub fn tick(&mut self, dt: f32, target: &mut T) -> bool {
+        if self.steps.is_empty() {
+            return true;
+        }
+        // a loop without duration would never make progress
+        let looping = self.total_duration() > 0.0 && self.repeat != TweenLoop::Once;
+
+        let mut dt = dt;
+        loop {
+            let index = if self.backwards {
+                self.steps.len() - 1 - self.current
+            } else {
+                self.current
+            };
+            let backwards = self.backwards;
+            let step = &mut self.steps[index];
+            let remaining = step.duration - self.elapsed;
+            let t = |elapsed: f32| {
+                let t = if step.duration > 0.0 {
+                    elapsed / step.duration
+                } else {
+                    1.0
+                };
+                if backwards {
+                    1.0 - t
+                } else {
+                    t
+                }
+            };
+            if dt < remaining {
+                self.elapsed += dt;
+                let t = t(self.elapsed);
+                step.apply(target, t);
+                return false;
+            }
+            let t = t(step.duration);
+            step.apply(target, t);
+            dt -= remaining.max(0.0);
+            self.elapsed = 0.0;
+            self.current += 1;
+
+            if self.current < self.steps.len() {
+                continue;
+            }
+            self.current = 0;
+            self.loops_done += 1;
+            match self.repeat {
+                _ if !looping => return true,
+                TweenLoop::Once => return true,
+                TweenLoop::Repeat(n) if self.loops_done >= n => return true,
+                TweenLoop::Repeat(_) | TweenLoop::RepeatForever => (),
+                TweenLoop::PingPongForever => self.backwards = !self.backwards,
+            }
+        }
+    }
+}
+
+fn finish_tween<T: Send + Sync + 'static>(
+    commands: &mut Commands,
+    completed_events: &mut EventWriter<TweenCompleted>,
+    entity: Entity,
+    tween: &Tween<T>,
+) {
+    commands.entity(entity).remove::<Tween<T>>();
+    if let Some(user_data) = tween.completed_event {
+        completed_events.send(TweenCompleted { entity, user_data });
+    }
+}
+
+pub fn component_tween_system<T: Component>(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    mut completed_events: EventWriter<TweenCompleted>,
+    mut query: Query<(Entity, &mut Tween<T>, &mut T)>,
+) {
+    // paused, some lenses (e.g. jitter) would change the target even without progress
+    if time.delta_seconds() == 0.0 {
+        return;
+    }
+    for (entity, mut tween, mut target) in query.iter_mut() {
+        if tween.tick(time.delta_seconds(), &mut target) {
+            finish_tween(&mut commands, &mut completed_events, entity, &tween);
+        }
+    }
+}
+
+pub fn asset_tween_system<T: Asset>(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    mut completed_events: EventWriter<TweenCompleted>,
+    mut assets: ResMut<Assets<T>>,
+    mut query: Query<(Entity, &mut Tween<T>, &Handle<T>)>,
+) {
+    // paused, some lenses (e.g. jitter) would change the target even without progress
+    if time.delta_seconds() == 0.0 {
+        return;
+    }
+    for (entity, mut tween, handle) in query.iter_mut() {
+        let target = match assets.get_mut(handle) {
+            Some(target) => target,
+            None => continue,
+        };
+        if tween.tick(time.delta_seconds(), target) {
+            finish_tween(&mut commands, &mut completed_events, entity, &tween);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::sim_time::{sim_time_system, SimTime};
+
+    // writes the eased ratio into the target
+    struct RatioLens;
+
+    impl Lens<f32> for RatioLens {
+        fn lerp(&mut self, target: &mut f32, ratio: f32) {
+            *target = ratio;
+        }
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn ease_endpoints() {
+        for ease in [
+            Ease::Linear,
+            Ease::QuadIn,
+            Ease::QuadOut,
+            Ease::QuadInOut,
+            Ease::CubicIn,
+            Ease::CubicOut,
+            Ease::CubicInOut,
+            Ease::SineInOut,
+        ] {
+            assert_near(ease.apply(0.0), 0.0);
+            assert_near(ease.apply(1.0), 1.0);
+            // clamped outside of the unit interval
+            assert_near(ease.apply(-1.0), 0.0);
+            assert_near(ease.apply(2.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn delay_before_first_step() {
+        let mut value = -1.0;
+        let mut tween = Tween::new(Ease::Linear, 1.0, RatioLens).with_delay(0.5);
+        assert!(!tween.tick(0.25, &mut value));
+        assert_near(value, -1.0);
+        assert!(!tween.tick(0.5, &mut value));
+        assert_near(value, 0.25);
+        assert!(tween.tick(1.0, &mut value));
+        assert_near(value, 1.0);
+    }
+
+    #[test]
+    fn repeat_count() {
+        let mut value = 0.0;
+        let mut tween = Tween::new(Ease::Linear, 1.0, RatioLens).with_repeat(TweenLoop::Repeat(3));
+        assert!(!tween.tick(1.0, &mut value));
+        assert!(!tween.tick(1.0, &mut value));
+        assert!(!tween.tick(0.5, &mut value));
+        assert_near(value, 0.5);
+        assert!(tween.tick(0.5, &mut value));
+        assert_near(value, 1.0);
+    }
+
+    #[test]
+    fn ping_pong_reverses() {
+        let mut value = 0.0;
+        let mut tween =
+            Tween::new(Ease::Linear, 1.0, RatioLens).with_repeat(TweenLoop::PingPongForever);
+        assert!(!tween.tick(0.75, &mut value));
+        assert_near(value, 0.75);
+        // on the way back after reaching the end
+        assert!(!tween.tick(0.5, &mut value));
+        assert_near(value, 0.75);
+        assert!(!tween.tick(0.5, &mut value));
+        assert_near(value, 0.25);
+        // and forward again
+        assert!(!tween.tick(0.5, &mut value));
+        assert_near(value, 0.25);
+    }
+
+    #[derive(Default)]
+    struct Completed(Vec<u64>);
+
+    fn count_completed_system(
+        mut events: EventReader<TweenCompleted>,
+        mut completed: ResMut<Completed>,
+    ) {
+        completed
+            .0
+            .extend(events.iter().map(|event| event.user_data));
+    }
+
+    #[test]
+    fn completed_event_fires_once() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<SimTime>()
+            .init_resource::<Completed>()
+            .add_event::<TweenCompleted>()
+            .add_system_to_stage(CoreStage::PreUpdate, sim_time_system)
+            .add_system(component_tween_system::<Transform>)
+            .add_system_to_stage(CoreStage::PostUpdate, count_completed_system);
+
+        let scale = ScaleLens {
+            start: Vec3::ONE,
+            end: Vec3::splat(2.0),
+        };
+        let entity = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(Tween::new(Ease::Linear, 0.01, scale).with_completed_event(7))
+            .id();
+
+        for _ in 0..20 {
+            app.update();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        assert_eq!(app.world.get_resource::<Completed>().unwrap().0, vec![7]);
+        assert!(app.world.get::<Tween<Transform>>(entity).is_none());
+        assert_eq!(
+            app.world.get::<Transform>(entity).unwrap().scale,
+            Vec3::splat(2.0)
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_mod_picking::PickableBundle;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auto_collider::AttachCollider,
+    combat::{line_of_sight, DamageEvent, Health, ATTACK_DAMAGE, ATTACK_RANGE},
+    fx::PlayerExplosion,
+    hex::HexCube,
+    sim_time::{sim_time_system, SimTime},
+};
+
+pub const TEAM_COLORS: [Color; 2] = [Color::GREEN, Color::CYAN];
+
+// maximum hex distance a player can move in one command
+pub const MAX_MOVE_DISTANCE: i32 = 1;
+
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Player {
+    pub team: u32,
+}
+
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Tile;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TurnState {
+    pub turn: u32,
+    pub active_team: u32,
+    pub num_teams: u32,
+}
+
+impl Default for TurnState {
+    fn default() -> Self {
+        TurnState {
+            turn: 0,
+            active_team: 0,
+            num_teams: 2,
+        }
+    }
+}
+
+impl TurnState {
+    pub fn advance(&mut self) {
+        self.active_team += 1;
+        if self.active_team >= self.num_teams {
+            self.active_team = 0;
+            self.turn += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct GlobalState {
+    pub tile_mesh: Handle<Mesh>,
+    pub tile_material: Handle<StandardMaterial>,
+    pub player_mesh: Option<Handle<Mesh>>,
+    pub tiles_root: Option<Entity>,
+    pub players_root: Option<Entity>,
+}
+
+pub fn spawn_tile(
+    commands: &mut Commands,
+    global_state: &GlobalState,
+    cube: HexCube,
+    height: f32,
+    material: Handle<StandardMaterial>,
+) -> Entity {
+    let pos = cube.to_odd_r_screen().extend(height).xzy();
+    let oddr = cube.to_odd_r();
+    let tile = commands
+        .spawn_bundle(PbrBundle {
+            transform: Transform::from_translation(pos),
+            mesh: global_state.tile_mesh.clone(),
+            material,
+            ..default()
+        })
+        .insert_bundle(PickableBundle::default())
+        .insert(AttachCollider::default())
+        .insert(RigidBody::KinematicPositionBased)
+        .insert(cube)
+        .insert(Tile)
+        .insert(Name::new(format!(
+            "tile.{}.{}",
+            oddr.x as i32, oddr.y as i32
+        )))
+        .id();
+
+    if let Some(tiles_root) = global_state.tiles_root {
+        commands.entity(tiles_root).add_child(tile);
+    }
+    tile
+}
+
+pub fn spawn_player(
+    commands: &mut Commands,
+    global_state: &GlobalState,
+    cube: HexCube,
+    team: u32,
+) -> Entity {
+    let oddr = cube.to_odd_r();
+    let player = commands
+        .spawn()
+        .insert(cube)
+        .insert(Player { team })
+        .insert(Health::default())
+        .insert(Name::new(format!(
+            "player.{}.{}",
+            oddr.x as i32, oddr.y as i32
+        )))
+        .id();
+
+    if let Some(players_root) = global_state.players_root {
+        commands.entity(players_root).add_child(player);
+    }
+    player
+}
+
+#[allow(clippy::type_complexity)]
+pub fn spawn_player_system(
+    mut commands: Commands,
+    mut global_state: ResMut<GlobalState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<
+        (
+            Entity,
+            &HexCube,
+            &Player,
+            Option<&Transform>,
+            Option<&RigidBody>,
+        ),
+        Added<Player>,
+    >,
+) {
+    for (entity, cube, player, transform, rigid_body) in query.iter() {
+        let v = cube.to_odd_r_screen().extend(0.0).xzy();
+
+        let mesh = global_state
+            .player_mesh
+            .get_or_insert_with(|| meshes.add(shape::Cube { size: 0.1 }.into()))
+            .clone();
+
+        let color = TEAM_COLORS[player.team as usize % TEAM_COLORS.len()];
+        let material = materials.add(StandardMaterial {
+            reflectance: 0.0,
+            emissive: color,
+            ..default()
+        });
+
+        // players restored from a savegame already come with their transform
+        let transform = transform
+            .cloned()
+            .unwrap_or_else(|| Transform::from_translation(v + Vec3::Y * 0.2));
+
+        commands
+            .entity(entity)
+            .insert_bundle(PbrBundle {
+                mesh,
+                material,
+                transform,
+                ..default()
+            })
+            .insert(rigid_body.cloned().unwrap_or(RigidBody::Dynamic))
+            .insert(Collider::cuboid(0.05, 0.05, 0.05))
+            .with_children(|commands| {
+                commands.spawn_bundle(PointLightBundle {
+                    point_light: PointLight {
+                        color,
+                        radius: 0.1,
+                        range: 1.0,
+                        intensity: 20.0,
+                        ..default()
+                    },
+                    ..default()
+                });
+            });
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GameCommand {
+    Move { from: HexCube, to: HexCube },
+    Attack { from: HexCube, target: HexCube },
+    EndTurn { team: u32 },
+}
+
+// a command issued by local input (or AI). Unless a networking plugin takes over the routing
+// (see NetworkedCommands) it is forwarded as GameCommand right away.
+pub struct CommandRequest(pub GameCommand);
+
+// the team controlled by local input. Absent in hotseat mode, where input controls the active team.
+pub struct LocalTeam(pub u32);
+
+// present while networking plugins are responsible for turning CommandRequests into GameCommands
+pub struct NetworkedCommands;
+
+// present on replicated clients: the game state is owned by the server, so local systems must not
+// change it (e.g. by resolving explosions).
+pub struct RemoteAuthority;
+
+// Time driving gameplay timers, like the countdown of exploding players. Follows SimTime, unless a
+// networking plugin advances it in fixed steps (see LockstepSession), so that timers expire in the
+// same tick on every peer.
+#[derive(Default)]
+pub struct GameTime {
+    delta: f32,
+    fixed_step: bool,
+}
+
+impl GameTime {
+    pub fn fixed_step() -> Self {
+        GameTime {
+            delta: 0.0,
+            fixed_step: true,
+        }
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        self.delta += delta;
+    }
+}
+
+pub fn game_time_system(sim_time: Res<SimTime>, mut game_time: ResMut<GameTime>) {
+    game_time.delta = if game_time.fixed_step {
+        0.0
+    } else {
+        sim_time.delta_seconds()
+    };
+}
+
+pub fn forward_command_requests_system(
+    networked: Option<Res<NetworkedCommands>>,
+    mut requests: EventReader<CommandRequest>,
+    mut game_commands: EventWriter<GameCommand>,
+) {
+    for CommandRequest(command) in requests.iter() {
+        if networked.is_none() {
+            game_commands.send(command.clone());
+        }
+    }
+}
+
+// all game state changes caused by player decisions go through here, so that they can be replayed
+// deterministically on every peer.
+pub fn apply_game_commands_system(
+    mut game_commands: EventReader<GameCommand>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut turn_state: ResMut<TurnState>,
+    tile_query: Query<&HexCube, (With<Tile>, Without<Player>)>,
+    mut player_query: Query<
+        (Entity, &mut HexCube, &Player, &mut Transform),
+        (Without<Tile>, Without<PlayerExplosion>),
+    >,
+) {
+    for command in game_commands.iter() {
+        // a turn is a single move or attack, or a pass
+        let ends_turn = match command {
+            GameCommand::Move { from, to } => {
+                if from.distance(to) > MAX_MOVE_DISTANCE || !tile_query.iter().any(|c| c == to) {
+                    info!("rejected move: {:?}", command);
+                    continue;
+                }
+                if player_query.iter().any(|(_, cube, _, _)| *cube == *to) {
+                    info!("rejected move, target occupied: {:?}", command);
+                    continue;
+                }
+                let mover = player_query.iter_mut().find(|(_, cube, player, _)| {
+                    **cube == *from && player.team == turn_state.active_team
+                });
+                match mover {
+                    Some((_, mut cube, _, mut transform)) => {
+                        *cube = *to;
+                        let v = to.to_odd_r_screen().extend(0.0).xzy();
+                        transform.translation = v + Vec3::Y * 0.2;
+                        true
+                    }
+                    None => {
+                        info!("rejected move, no player of the active team: {:?}", command);
+                        false
+                    }
+                }
+            }
+            GameCommand::Attack { from, target } => {
+                let attacker = player_query
+                    .iter()
+                    .find(|(_, cube, player, _)| {
+                        **cube == *from && player.team == turn_state.active_team
+                    })
+                    .map(|(entity, _, _, _)| entity);
+                let target_entity = player_query
+                    .iter()
+                    .find(|(_, cube, player, _)| {
+                        **cube == *target && player.team != turn_state.active_team
+                    })
+                    .map(|(entity, _, _, _)| entity);
+
+                // other players and holes in the board block the line of fire
+                let visible = line_of_sight(*from, *target, |cube| {
+                    !tile_query.iter().any(|c| c == cube)
+                        || player_query.iter().any(|(_, c, _, _)| c == cube)
+                });
+
+                match (attacker, target_entity) {
+                    (Some(attacker), Some(target_entity))
+                        if from.distance(target) <= ATTACK_RANGE && visible =>
+                    {
+                        damage_events.send(DamageEvent {
+                            target: target_entity,
+                            source: Some(attacker),
+                            amount: ATTACK_DAMAGE,
+                        });
+                        true
+                    }
+                    _ => {
+                        info!("rejected attack: {:?}", command);
+                        false
+                    }
+                }
+            }
+            GameCommand::EndTurn { team } => *team == turn_state.active_team,
+        };
+        if ends_turn {
+            turn_state.advance();
+            info!("turn {} team {}", turn_state.turn, turn_state.active_team);
+        }
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GameSystem {
+    ApplyCommands,
+}
+
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GlobalState>()
+            .init_resource::<TurnState>()
+            .init_resource::<GameTime>()
+            .add_event::<CommandRequest>()
+            .add_event::<GameCommand>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                game_time_system.after(sim_time_system),
+            )
+            .add_system(spawn_player_system)
+            .add_system(forward_command_requests_system.before(GameSystem::ApplyCommands))
+            .add_system(apply_game_commands_system.label(GameSystem::ApplyCommands));
+    }
+}
@@ -1,8 +1,9 @@
 use bevy::{
-    prelude::{Component, Vec2},
+    prelude::{Component, Vec2, Vec3},
     reflect::Reflect,
 };
 use num_traits::Num;
+use serde::{Deserialize, Serialize};
 
 // pub mod editor;
 // pub mod io;
@@ -10,7 +11,9 @@ use num_traits::Num;
 // pub mod wavefunction;
 
 // mostly based on https://www.redblobgames.com/grids/hexagons/
-#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq, Reflect, Component)]
+#[derive(
+    Default, Debug, Clone, Copy, Hash, PartialEq, Eq, Reflect, Component, Serialize, Deserialize,
+)]
 pub struct HexCube {
     pub x: i32,
     pub y: i32,
@@ -98,12 +101,20 @@ impl HexCube {
     }
 
     pub fn distance_between(a: &HexCube, b: &HexCube) -> i32 {
-        (a.x - b.x).abs() + (a.y - b.y).abs() + (a.z - b.z).abs() / 2
+        ((a.x - b.x).abs() + (a.y - b.y).abs() + (a.z - b.z).abs()) / 2
     }
     pub fn distance(&self, b: &HexCube) -> i32 {
         Self::distance_between(self, b)
     }
 
+    // all cubes within `radius` of self, including self
+    pub fn range(self, radius: i32) -> impl Iterator<Item = HexCube> {
+        (-radius..=radius).flat_map(move |x| {
+            (((-radius).max(-x - radius))..=(radius.min(-x + radius)))
+                .map(move |y| self + HexCube { x, y, z: -x - y })
+        })
+    }
+
     pub fn linedraw_between(a: &HexCube, b: &HexCube) -> (i32, [HexCube; 20]) {
         let n = Self::distance_between(a, b);
         let mut res = [HexCube::default(); 20];
@@ -116,6 +127,15 @@ impl HexCube {
     }
 }
 
+// cell containing a world space position, the inverse of to_odd_r_screen().extend(h).xzy()
+pub fn world_to_cube(pos: Vec3) -> HexCube {
+    // on screen x = cube.x + cube.z / 2 and y = cube.z * 0.75 (see to_odd_r_screen), undo that for
+    // fractional cube coordinates and round those to the nearest cell.
+    let z = pos.z / 0.75;
+    let x = pos.x - z / 2.0;
+    HexCube::round(x, -x - z, z)
+}
+
 // impl From<&Cube> for Cube {
 //     fn from(c: &Cube) -> Self {
 //         *c
@@ -263,3 +283,50 @@ impl Iterator for CubeLinedraw {
 pub mod prelude {
     pub use super::{HexAxial, HexCube};
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3Swizzles;
+
+    use super::*;
+
+    #[test]
+    fn world_to_cube_roundtrip() {
+        for cube in HexCube::zero().range(4) {
+            let center = cube.to_odd_r_screen().extend(0.3).xzy();
+            assert_eq!(world_to_cube(center), cube);
+            // close to the corners of the cell, beyond the inscribed rectangle
+            for offset in [
+                Vec3::new(0.0, 0.0, 0.45),
+                Vec3::new(0.0, 0.0, -0.45),
+                Vec3::new(0.45, 0.0, 0.2),
+                Vec3::new(-0.45, 0.0, -0.2),
+            ] {
+                assert_eq!(world_to_cube(center + offset), cube);
+            }
+        }
+    }
+
+    #[test]
+    fn world_to_cube_between_cells() {
+        let a = HexCube::zero();
+        let b = HexCube::new(0, -1, 1);
+        let pa = a.to_odd_r_screen().extend(0.0).xzy();
+        let pb = b.to_odd_r_screen().extend(0.0).xzy();
+        assert_eq!(world_to_cube(pa.lerp(pb, 0.45)), a);
+        assert_eq!(world_to_cube(pa.lerp(pb, 0.55)), b);
+    }
+
+    #[test]
+    fn distance_between() {
+        let a = HexCube::new(1, -3, 2);
+        let b = HexCube::new(-2, 1, 1);
+        assert_eq!(HexCube::distance_between(&a, &b), 4);
+        assert_eq!(HexCube::distance_between(&b, &a), 4);
+        assert_eq!(a.distance(&b), 4);
+        assert_eq!(HexCube::distance_between(&a, &a), 0);
+        for cube in HexCube::zero().range(3) {
+            assert!(HexCube::distance_between(&HexCube::zero(), &cube) <= 3);
+        }
+    }
+}
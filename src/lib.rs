@@ -2,12 +2,23 @@
 
 use bevy::prelude::*;
 
+pub mod ai;
 pub mod auto_collider;
+pub mod combat;
 pub mod debug_hud;
+pub mod destruction;
 pub mod fx;
+pub mod game;
 pub mod hex;
 // pub mod hud;
+pub mod net;
+pub mod physics_config;
+pub mod physics_debug;
+pub mod physics_events;
+pub mod picking;
 pub mod property;
+pub mod savegame;
+pub mod sim_time;
 
 pub mod shape {
     use bevy::{
@@ -15,12 +26,36 @@ pub mod shape {
         render::mesh::{Indices, PrimitiveTopology},
     };
 
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub struct HexPlane {
         pub w: f32,
         pub h: f32,
         pub e: f32,
     }
 
+    impl HexPlane {
+        // upper and lower corners of the hex prism
+        pub fn corners(&self) -> Vec<Vec3> {
+            let h2 = self.h / 2.0;
+            let h4 = self.h / 4.0;
+            let w2 = self.w / 2.0;
+            let e2 = self.e / 2.0;
+            [e2, -e2]
+                .iter()
+                .flat_map(|y| {
+                    [
+                        Vec3::new(0.0, *y, h2),
+                        Vec3::new(w2, *y, h4),
+                        Vec3::new(w2, *y, -h4),
+                        Vec3::new(0.0, *y, -h2),
+                        Vec3::new(-w2, *y, -h4),
+                        Vec3::new(-w2, *y, h4),
+                    ]
+                })
+                .collect()
+        }
+    }
+
     impl From<HexPlane> for Mesh {
         fn from(plane: HexPlane) -> Self {
             // let extent = plane.size / 2.0;
@@ -1,28 +1,49 @@
 use game2::{
-    auto_collider::AttachCollider,
-    fx::{DoRotate, PlayerExplosion},
+    ai::{AiController, SearchStrategy},
+    destruction::DestroyedTile,
+    fx::{flip::FlipTile, pool::PooledParticle, shake::CameraShake, PlayerExplosion},
+    game::{
+        spawn_player, spawn_tile, CommandRequest, GameCommand, GlobalState, LocalTeam, Player,
+        Tile, TurnState,
+    },
     hex::HexCube,
+    picking::PhysicsPickingCamera,
     property::PropertyValue,
+    savegame::{LoadGameEvent, SaveGameEvent},
 };
 
 use bevy::{
+    app::{ScheduleRunnerPlugin, ScheduleRunnerSettings},
     diagnostic::{
         DiagnosticsPlugin, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
         LogDiagnosticsPlugin,
     },
     input::system::exit_on_esc_system,
-    math::Vec3Swizzles,
     prelude::*,
+    render::settings::WgpuSettings,
     window::PresentMode,
+    winit::WinitPlugin,
 };
 use bevy_egui::{egui, EguiContext, EguiPlugin};
 use bevy_mod_picking::{
-    InteractablePickingPlugin, PickableBundle, PickingCameraBundle, PickingEvent, PickingPlugin,
+    InteractablePickingPlugin, PickingCameraBundle, PickingEvent, PickingPlugin,
 };
 use bevy_rapier3d::prelude::*;
 use rand::prelude::*;
+use std::{net::SocketAddr, time::Duration};
 
 fn main() {
+    // usage:
+    //   game2 --lockstep <local addr> <peer addr> <team>
+    //   game2 --server <addr>
+    //   game2 --client <local addr> <server addr>
+    //   game2 [--ai greedy|minimax|mcts] [--physics-picking]
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() >= 3 && args[1] == "--server" {
+        run_server(args[2].parse().expect("bad server address"));
+        return;
+    }
+
     let mut app = App::new();
     app.insert_resource(WindowDescriptor {
         present_mode: PresentMode::Fifo,
@@ -40,31 +61,66 @@ fn main() {
     app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         .add_plugin(FrameTimeDiagnosticsPlugin)
         .add_plugin(EntityCountDiagnosticsPlugin);
-    // app.add_plugin(RapierDebugRenderPlugin::default());
+    app.add_plugin(game2::physics_debug::PhysicsDebugPlugin);
 
-    app.add_plugin(game2::auto_collider::AutoColliderPlugin);
-
-    app.add_plugin(game2::fx::FxPlugin);
+    add_game_plugins(&mut app);
+    app.add_plugin(game2::savegame::SaveGamePlugin);
 
     app.add_plugin(game2::debug_hud::DebugHudPlugin);
-    app.add_plugin(game2::property::PropertyPlugin);
 
     // app.add_plugins(DefaultPickingPlugins) // <- Adds Picking, Interaction, and Highlighting plugins.
     //     .add_plugin(DebugCursorPickingPlugin) // <- Adds the green debug cursor.
     //     .add_plugin(DebugEventsPickingPlugin); // <- Adds debug event logging.
 
-    app.add_plugin(PickingPlugin)
-        .add_plugin(InteractablePickingPlugin);
+    // --physics-picking: raycast into the rapier world instead of the render meshes
+    if args.iter().any(|arg| arg == "--physics-picking") {
+        app.add_plugin(game2::picking::PhysicsPickingPlugin);
+    } else {
+        app.add_plugin(PickingPlugin)
+            .add_plugin(InteractablePickingPlugin);
+    }
 
     app.add_system_to_stage(CoreStage::PostUpdate, picking_events_system);
 
     // app.add_system(rotate_system);
-    app.add_startup_system(setup);
+    app.add_startup_system(setup.label("setup"));
+    app.add_startup_system(setup_camera);
     app.add_system(cube_spawn_system);
-    app.add_system(material_properties_ui_system)
-        .init_resource::<GlobalState>();
+    app.add_system(material_properties_ui_system);
+    app.add_system(savegame_keyboard_system);
+    app.add_system(end_turn_keyboard_system);
+    app.add_system(game2::sim_time::sim_time_keyboard_system);
+
+    if args.len() >= 4 && args[1] == "--client" {
+        // the board is replicated from the server
+        app.add_plugin(game2::net::client::ClientPlugin {
+            local_addr: args[2].parse().expect("bad local address"),
+            server_addr: args[3].parse().expect("bad server address"),
+        });
+    } else {
+        app.add_startup_system(setup_board.after("setup"));
+    }
+
+    if args.len() >= 5 && args[1] == "--lockstep" {
+        app.add_plugin(game2::net::lockstep::LockstepPlugin {
+            local_addr: args[2].parse().expect("bad local address"),
+            peer_addr: args[3].parse().expect("bad peer address"),
+            local_team: args[4].parse().expect("bad team"),
+        });
+    }
 
-    app.add_system(spawn_player_system);
+    // computer controlled second team: --ai [greedy|minimax|mcts]
+    if let Some(i) = args.iter().position(|arg| arg == "--ai") {
+        let strategy = match args.get(i + 1).map(String::as_str) {
+            Some("greedy") => SearchStrategy::Greedy,
+            Some("mcts") => SearchStrategy::Mcts { exploration: 1.4 },
+            _ => SearchStrategy::Minimax { max_depth: 4 },
+        };
+        app.world
+            .spawn()
+            .insert(AiController::new(1, strategy, Duration::from_millis(500)))
+            .insert(Name::new("ai.1"));
+    }
 
     #[cfg(feature = "inspector")]
     {
@@ -74,13 +130,56 @@ fn main() {
     app.run();
 }
 
+fn add_game_plugins(app: &mut App) {
+    app.add_plugin(game2::auto_collider::AutoColliderPlugin);
+
+    app.add_plugin(game2::fx::FxPlugin);
+    app.add_plugin(game2::game::GamePlugin);
+    app.add_plugin(game2::combat::CombatPlugin);
+    app.add_plugin(game2::destruction::DestructionPlugin);
+    app.add_plugin(game2::physics_events::PhysicsEventsPlugin);
+    app.add_plugin(game2::physics_config::PhysicsConfigPlugin);
+    app.add_plugin(game2::sim_time::SimTimePlugin);
+    app.add_plugin(game2::ai::AiPlugin);
+
+    app.add_plugin(game2::property::PropertyPlugin);
+}
+
+// dedicated server: no window and no rendering, the board and players live here and are
+// replicated to the clients.
+fn run_server(addr: SocketAddr) {
+    let mut app = App::new();
+    app.insert_resource(WgpuSettings {
+        backends: None,
+        ..default()
+    })
+    .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+        1.0 / 60.0,
+    )))
+    .add_plugins_with(DefaultPlugins, |group| group.disable::<WinitPlugin>())
+    .add_plugin(ScheduleRunnerPlugin);
+
+    app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default());
+    add_game_plugins(&mut app);
+    app.add_plugin(game2::net::server::ServerPlugin { addr });
+
+    app.add_startup_system(setup.label("setup"));
+    app.add_startup_system(setup_board.after("setup"));
+    app.run();
+}
+
+#[allow(clippy::too_many_arguments)]
 fn picking_events_system(
-    mut commands: Commands,
     mut events: EventReader<PickingEvent>,
-    rotating: Query<Entity, With<DoRotate>>,
-    tile_pos_query: Query<(&Transform, &HexCube), Without<Player>>,
-    player_query: Query<(Entity, &HexCube), With<Player>>,
+    mut selected: Local<Option<HexCube>>,
+    turn_state: Res<TurnState>,
+    local_team: Option<Res<LocalTeam>>,
+    rotating: Query<Entity, With<FlipTile>>,
+    tile_pos_query: Query<&HexCube, With<Tile>>,
+    player_query: Query<(&HexCube, &Player), Without<PlayerExplosion>>,
+    mut command_requests: EventWriter<CommandRequest>,
 ) {
+    let our_turn = local_team.map_or(true, |team| team.0 == turn_state.active_team);
     for event in events.iter() {
         match event {
             PickingEvent::Selection(e) => info!("A selection event happened: {:?}", e),
@@ -92,17 +191,31 @@ fn picking_events_system(
                 // }
             }
             PickingEvent::Clicked(e) => {
-                if !rotating.contains(*e) {
+                if !rotating.contains(*e) && our_turn {
                     // commands.entity(*e).insert(DoRotate::default());
-                    if let Ok((Transform { translation: _, .. }, cube)) = tile_pos_query.get(*e) {
-                        for (player_entity, player_cube) in player_query.iter() {
-                            if player_cube == cube {
-                                // commands.entity(player_entity).despawn_recursive();
-                                commands
-                                    .entity(player_entity)
-                                    .insert(PlayerExplosion { time_left: 1.0 });
-                                break;
+                    if let Ok(cube) = tile_pos_query.get(*e) {
+                        let occupant = player_query
+                            .iter()
+                            .find(|(player_cube, _)| *player_cube == cube)
+                            .map(|(_, player)| player.team);
+
+                        match (*selected, occupant) {
+                            (_, Some(team)) if team == turn_state.active_team => {
+                                *selected = Some(*cube);
+                            }
+                            (Some(from), Some(_)) => {
+                                command_requests.send(CommandRequest(GameCommand::Attack {
+                                    from,
+                                    target: *cube,
+                                }));
+                                *selected = None;
+                            }
+                            (Some(from), None) => {
+                                command_requests
+                                    .send(CommandRequest(GameCommand::Move { from, to: *cube }));
+                                *selected = None;
                             }
+                            (None, _) => (),
                         }
                     }
                 }
@@ -111,36 +224,61 @@ fn picking_events_system(
     }
 }
 
-#[derive(Default)]
-struct GlobalState {
-    tile_material: Handle<StandardMaterial>,
-    player_mesh: Option<Handle<Mesh>>,
+fn end_turn_keyboard_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    turn_state: Res<TurnState>,
+    local_team: Option<Res<LocalTeam>>,
+    mut command_requests: EventWriter<CommandRequest>,
+) {
+    let our_turn = local_team.map_or(true, |team| team.0 == turn_state.active_team);
+    if keyboard_input.just_pressed(KeyCode::Return) && our_turn {
+        command_requests.send(CommandRequest(GameCommand::EndTurn {
+            team: turn_state.active_team,
+        }));
+    }
 }
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>,
-    mut global_state: ResMut<GlobalState>,
+fn savegame_keyboard_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut save_events: EventWriter<SaveGameEvent>,
+    mut load_events: EventWriter<LoadGameEvent>,
 ) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        save_events.send(SaveGameEvent("quicksave.ron".into()));
+    }
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        load_events.send(LoadGameEvent("quicksave.ron".into()));
+    }
+}
+
+fn setup_camera(mut commands: Commands) {
     let camera_pos = Vec3::new(0.0, 2.0, 0.0);
     let camera_look = Vec3::new(2.0, -1.0, 2.0);
     // let camera_pos = Vec3::new(-20.0, 2.0, -20.0);
     // let camera_look = Vec3::new(2.0, -1.0, 2.0);
 
-    commands
-        .spawn()
-        .insert(Name::new("blub"))
-        .insert(PropertyValue::String("x".into()));
-
     commands
         .spawn_bundle(PerspectiveCameraBundle {
             transform: Transform::from_translation(camera_pos)
                 .looking_at(camera_pos + camera_look, Vec3::Y),
             ..default()
         })
-        .insert_bundle(PickingCameraBundle::default());
+        .insert_bundle(PickingCameraBundle::default())
+        .insert(PhysicsPickingCamera)
+        .insert(CameraShake::default());
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    mut global_state: ResMut<GlobalState>,
+) {
+    commands
+        .spawn()
+        .insert(Name::new("blub"))
+        .insert(PropertyValue::String("x".into()));
 
     let mesh = meshes.add(shape::Plane::default().into());
     commands.spawn_bundle(PbrBundle { mesh, ..default() });
@@ -156,30 +294,37 @@ fn setup(
 
     let _mesh_inst = meshes.get(mesh.clone());
     // info!("mesh: {:?}", mesh_inst);
-    let mut rng = rand::thread_rng();
 
     let mut material: StandardMaterial = Color::WHITE.into();
     material.perceptual_roughness = 0.4;
     material.metallic = 0.6;
 
-    let material = materials.add(material);
-    global_state.tile_material = material.clone();
-    let field_size = 11;
+    global_state.tile_material = materials.add(material);
+    global_state.tile_mesh = mesh;
 
-    let tiles_root = commands
-        .spawn_bundle(TransformBundle::default())
-        .insert(Name::new("tiles"))
-        .id();
+    global_state.tiles_root = Some(
+        commands
+            .spawn_bundle(TransformBundle::default())
+            .insert(Name::new("tiles"))
+            .id(),
+    );
 
-    let players_root = commands
-        .spawn_bundle(TransformBundle::default())
-        .insert(Name::new("players"))
-        .id();
+    global_state.players_root = Some(
+        commands
+            .spawn_bundle(TransformBundle::default())
+            .insert(Name::new("players"))
+            .id(),
+    );
+}
+
+fn setup_board(mut commands: Commands, global_state: Res<GlobalState>) {
+    let mut rng = rand::thread_rng();
+    let field_size = 11;
+    let material = global_state.tile_material.clone();
 
     for y in 0..field_size {
         for x in 0..field_size {
             let cube = HexCube::from_odd_r(Vec2::new(x as f32, y as f32));
-            let pos = cube.to_odd_r_screen().extend(0.0).xzy();
             // info!("pos: {:?}", pos);
             let _color = if x == 0 {
                 Color::RED
@@ -189,35 +334,15 @@ fn setup(
                 *game2::COLORS.choose(&mut rng).unwrap()
             };
 
-            let mut ec = commands.spawn();
-            let tile = ec
-                .insert_bundle(PbrBundle {
-                    transform: Transform::from_translation(pos),
-
-                    mesh: mesh.clone(),
-                    material: material.clone(),
-                    ..default()
-                })
-                .insert_bundle(PickableBundle::default())
-                .insert(AttachCollider)
-                .insert(RigidBody::KinematicPositionBased)
-                .insert(cube)
-                .insert(Name::new(format!("tile.{}.{}", x, y)))
-                .id();
+            let tile = spawn_tile(&mut commands, &global_state, cube, 0.0, material.clone());
 
             if x == 5 && y == 5 {
-                ec.insert(DoRotate::default());
+                commands.entity(tile).insert(FlipTile::new(0));
             }
-            commands.entity(tiles_root).add_child(tile);
 
             if (x % 2 + y) % 2 == 0 {
-                let player = commands
-                    .spawn()
-                    .insert(cube)
-                    .insert(Player {})
-                    .insert(Name::new(format!("player.{}.{}", x, y)))
-                    .id();
-                commands.entity(players_root).add_child(player);
+                let team = if y < field_size / 2 { 0 } else { 1 };
+                spawn_player(&mut commands, &global_state, cube, team);
             }
             // if x == 5 && y == 5 {
             //     commands.spawn_bundle(PointLightBundle {
@@ -245,7 +370,16 @@ fn cube_spawn_system(
     mut _meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut _cube_handle: Local<Option<Handle<Mesh>>>,
-    despawn_query: Query<(Entity, &Transform, &Handle<StandardMaterial>), With<Collider>>,
+    // destroyed tiles share their material with the board, they are handled by DestructionPlugin.
+    // Pooled particles share theirs per color, they are returned to the pool by the FxPlugin.
+    despawn_query: Query<
+        (Entity, &Transform, &Handle<StandardMaterial>),
+        (
+            With<Collider>,
+            Without<DestroyedTile>,
+            Without<PooledParticle>,
+        ),
+    >,
 ) {
     // let mut num_colliders = 0;
     for (entity, Transform { translation, .. }, material) in despawn_query.iter() {
@@ -330,52 +464,3 @@ fn material_properties_ui_system(
         }
     });
 }
-
-#[derive(Component)]
-struct Player {}
-fn spawn_player_system(
-    mut commands: Commands,
-    mut global_state: ResMut<GlobalState>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    query: Query<(Entity, &HexCube), Added<Player>>,
-) {
-    for (entity, cube) in query.iter() {
-        let v = cube.to_odd_r_screen().extend(0.0).xzy();
-
-        let mesh = global_state
-            .player_mesh
-            .get_or_insert_with(|| meshes.add(shape::Cube { size: 0.1 }.into()))
-            .clone();
-
-        let material = materials.add(StandardMaterial {
-            reflectance: 0.0,
-            emissive: Color::GREEN,
-            ..default()
-        });
-
-        commands
-            .entity(entity)
-            .insert_bundle(PbrBundle {
-                mesh,
-                material,
-                transform: Transform::from_translation(v + Vec3::Y * 0.2),
-                ..default()
-            })
-            .insert(RigidBody::Dynamic)
-            .insert(Collider::cuboid(0.05, 0.05, 0.05))
-            .with_children(|commands| {
-                commands.spawn_bundle(PointLightBundle {
-                    point_light: PointLight {
-                        color: Color::GREEN,
-                        radius: 0.1,
-                        range: 1.0,
-                        intensity: 20.0,
-                        ..default()
-                    },
-                    ..default()
-                });
-            });
-        // if global_state.player_mesh.id == 0 {}
-    }
-}
@@ -0,0 +1,250 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::*;
+
+use super::{
+    replication::{ClientMessage, NetId, ReplicatedKind, ServerMessage, WorldState},
+    UdpTransport,
+};
+use crate::{
+    fx::PlayerExplosion,
+    game::{
+        spawn_player, spawn_tile, CommandRequest, GlobalState, LocalTeam, NetworkedCommands,
+        RemoteAuthority, TurnState,
+    },
+    sim_time::SimTime,
+};
+
+pub const SERVER_TIMEOUT: f64 = 5.0;
+pub const JOIN_RETRY_INTERVAL: f64 = 1.0;
+
+// number of reconstructed snapshots kept as potential delta baselines
+const SNAPSHOT_HISTORY: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+}
+
+pub struct ClientState {
+    transport: UdpTransport,
+    server_addr: SocketAddr,
+    token: u64,
+    status: ConnectionStatus,
+    last_heard: f64,
+    last_join: Option<f64>,
+    snapshots: BTreeMap<u32, WorldState>,
+    applied: Option<u32>,
+    entities: HashMap<NetId, Entity>,
+}
+
+impl ClientState {
+    pub fn new(transport: UdpTransport, server_addr: SocketAddr) -> Self {
+        ClientState {
+            transport,
+            server_addr,
+            token: rand::random(),
+            status: ConnectionStatus::Connecting,
+            last_heard: 0.0,
+            last_join: None,
+            snapshots: BTreeMap::new(),
+            applied: None,
+            entities: HashMap::default(),
+        }
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.status
+    }
+
+    pub fn disconnect(&mut self) {
+        if let Err(err) = self
+            .transport
+            .send(&ClientMessage::Disconnect, self.server_addr)
+        {
+            warn!("failed to send disconnect: {}", err);
+        }
+        self.status = ConnectionStatus::Connecting;
+        self.last_join = None;
+    }
+
+    fn send(&self, msg: &ClientMessage) {
+        if let Err(err) = self.transport.send(msg, self.server_addr) {
+            debug!("client send failed: {}", err);
+        }
+    }
+}
+
+pub fn client_connection_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut client: ResMut<ClientState>,
+) {
+    let now = time.seconds_since_startup();
+    if client.status == ConnectionStatus::Connected && now - client.last_heard > SERVER_TIMEOUT {
+        warn!("server timed out, reconnecting");
+        client.status = ConnectionStatus::Connecting;
+        client.last_join = None;
+        commands.remove_resource::<LocalTeam>();
+    }
+
+    if client.status == ConnectionStatus::Connecting
+        && client
+            .last_join
+            .map_or(true, |last_join| now - last_join > JOIN_RETRY_INTERVAL)
+    {
+        let token = client.token;
+        client.send(&ClientMessage::Join { token });
+        client.last_join = Some(now);
+    }
+}
+
+pub fn client_intent_system(client: Res<ClientState>, mut requests: EventReader<CommandRequest>) {
+    for CommandRequest(command) in requests.iter() {
+        if client.status == ConnectionStatus::Connected {
+            client.send(&ClientMessage::Intent(command.clone()));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn client_receive_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut client: ResMut<ClientState>,
+    global_state: Res<GlobalState>,
+    mut turn_state: ResMut<TurnState>,
+    mut query: Query<(&mut Transform, Option<&PlayerExplosion>)>,
+) {
+    let now = time.seconds_since_startup();
+    let mut latest = None;
+    while let Some((msg, addr)) = client.transport.recv::<ServerMessage>() {
+        if addr != client.server_addr {
+            continue;
+        }
+        client.last_heard = now;
+        match msg {
+            ServerMessage::Welcome { team } => {
+                info!("joined server as team {:?}", team);
+                client.status = ConnectionStatus::Connected;
+                // the server starts over with a complete snapshot
+                client.applied = None;
+                client.snapshots.clear();
+                match team {
+                    Some(team) => commands.insert_resource(LocalTeam(team)),
+                    // spectator
+                    None => commands.remove_resource::<LocalTeam>(),
+                }
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                if client
+                    .applied
+                    .map_or(false, |applied| snapshot.id <= applied)
+                {
+                    continue;
+                }
+                let baseline = snapshot.baseline.and_then(|id| client.snapshots.get(&id));
+                match snapshot.apply(baseline) {
+                    Some(state) => {
+                        client.snapshots.insert(snapshot.id, state);
+                        client.applied = Some(snapshot.id);
+                        *turn_state = snapshot.turn_state.clone();
+                        latest = Some(snapshot.id);
+                    }
+                    None => debug!("dropping snapshot {}: baseline missing", snapshot.id),
+                }
+            }
+        }
+    }
+
+    let latest = match latest {
+        Some(latest) => latest,
+        None => return,
+    };
+    client.send(&ClientMessage::Ack { snapshot: latest });
+    while client.snapshots.len() > SNAPSHOT_HISTORY {
+        let oldest = *client.snapshots.keys().next().unwrap();
+        client.snapshots.remove(&oldest);
+    }
+
+    let client = &mut *client;
+    let state = &client.snapshots[&latest];
+
+    client.entities.retain(|id, entity| {
+        let keep = state.contains_key(id);
+        if !keep && query.get(*entity).is_ok() {
+            commands.entity(*entity).despawn_recursive();
+        }
+        keep
+    });
+
+    for (id, entity_state) in state.iter() {
+        let transform = Transform {
+            translation: entity_state.translation.into(),
+            rotation: Quat::from_array(entity_state.rotation),
+            ..default()
+        };
+        match client.entities.get(id) {
+            Some(entity) => {
+                // the entity may already be gone locally, e.g. when the explosion finished before
+                // the server removed the player.
+                if let Ok((mut current, exploding)) = query.get_mut(*entity) {
+                    // scale is left alone, the explosion effect animates it locally
+                    current.translation = transform.translation;
+                    current.rotation = transform.rotation;
+                    let mut ec = commands.entity(*entity);
+                    ec.insert(entity_state.cube);
+                    if let (Some(time_left), None) = (entity_state.explosion, exploding) {
+                        ec.insert(PlayerExplosion { time_left });
+                    }
+                }
+            }
+            None => {
+                let entity = match entity_state.kind {
+                    ReplicatedKind::Tile => spawn_tile(
+                        &mut commands,
+                        &global_state,
+                        entity_state.cube,
+                        entity_state.translation[1],
+                        global_state.tile_material.clone(),
+                    ),
+                    ReplicatedKind::Player { team } => {
+                        let entity =
+                            spawn_player(&mut commands, &global_state, entity_state.cube, team);
+                        // the server simulates the physics, players just follow the snapshots
+                        commands
+                            .entity(entity)
+                            .insert(RigidBody::KinematicPositionBased);
+                        entity
+                    }
+                };
+                let mut ec = commands.entity(entity);
+                ec.insert(*id).insert(transform);
+                if let Some(time_left) = entity_state.explosion {
+                    ec.insert(PlayerExplosion { time_left });
+                }
+                client.entities.insert(*id, entity);
+            }
+        }
+    }
+}
+
+pub struct ClientPlugin {
+    pub local_addr: SocketAddr,
+    pub server_addr: SocketAddr,
+}
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        let transport = UdpTransport::bind(self.local_addr).expect("failed to bind client socket");
+        app.insert_resource(ClientState::new(transport, self.server_addr))
+            .insert_resource(NetworkedCommands)
+            .insert_resource(RemoteAuthority)
+            .insert_resource(SimTime::locked())
+            .add_system(client_connection_system)
+            .add_system(client_intent_system)
+            .add_system(client_receive_system);
+    }
+}
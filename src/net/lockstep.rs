@@ -0,0 +1,375 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use super::{state_hash, UdpTransport};
+use crate::{
+    combat::Health,
+    fx::PlayerExplosion,
+    game::{
+        CommandRequest, GameCommand, GameSystem, GameTime, LocalTeam, NetworkedCommands, Player,
+        TurnState,
+    },
+    hex::HexCube,
+    sim_time::SimTime,
+};
+
+// commands issued during tick n are executed on all peers in tick n + INPUT_DELAY
+pub const INPUT_DELAY: u32 = 3;
+pub const TICK_LENGTH: f32 = 0.1;
+
+// upper bound for the number of unacknowledged bundles resent per packet
+const MAX_BUNDLES_PER_PACKET: usize = 32;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TickCommands {
+    pub tick: u32,
+    pub commands: Vec<GameCommand>,
+    // hash of the game state at the tick the commands were issued (tick - INPUT_DELAY)
+    pub state_hash: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LockstepPacket {
+    // all bundles before this tick have been received by the sender
+    pub received_until: u32,
+    pub bundles: Vec<TickCommands>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DesyncEvent {
+    pub tick: u32,
+    pub local_hash: u64,
+    pub remote_hash: u64,
+}
+
+pub struct LockstepSession {
+    transport: UdpTransport,
+    peer_addr: SocketAddr,
+    local_team: u32,
+    tick: u32,
+    timer: f32,
+    issued_tick: Option<u32>,
+    pending: Vec<GameCommand>,
+    unacked: BTreeMap<u32, TickCommands>,
+    local: BTreeMap<u32, Vec<GameCommand>>,
+    remote: BTreeMap<u32, Vec<GameCommand>>,
+    remote_next: u32,
+    local_hashes: HashMap<u32, u64>,
+    remote_hashes: HashMap<u32, u64>,
+}
+
+impl LockstepSession {
+    pub fn new(transport: UdpTransport, peer_addr: SocketAddr, local_team: u32) -> Self {
+        LockstepSession {
+            transport,
+            peer_addr,
+            local_team,
+            tick: 0,
+            timer: 0.0,
+            issued_tick: None,
+            pending: Vec::new(),
+            unacked: BTreeMap::new(),
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            remote_next: INPUT_DELAY,
+            local_hashes: HashMap::default(),
+            remote_hashes: HashMap::default(),
+        }
+    }
+
+    // the next tick to be executed
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.transport.local_addr()
+    }
+
+    fn receive(&mut self) {
+        while let Some((packet, addr)) = self.transport.recv::<LockstepPacket>() {
+            if addr != self.peer_addr {
+                warn!("ignoring lockstep packet from unknown peer {}", addr);
+                continue;
+            }
+            let acked: Vec<_> = self
+                .unacked
+                .range(..packet.received_until)
+                .map(|(tick, _)| *tick)
+                .collect();
+            for tick in acked {
+                self.unacked.remove(&tick);
+            }
+
+            for bundle in packet.bundles {
+                if bundle.tick < self.remote_next || self.remote.contains_key(&bundle.tick) {
+                    continue;
+                }
+                self.remote_hashes
+                    .insert(bundle.tick - INPUT_DELAY, bundle.state_hash);
+                self.remote.insert(bundle.tick, bundle.commands);
+            }
+            while self.remote.contains_key(&self.remote_next) {
+                self.remote_next += 1;
+            }
+        }
+    }
+
+    fn issue(&mut self, state_hash: u64) {
+        let tick = self.tick + INPUT_DELAY;
+        let commands = std::mem::take(&mut self.pending);
+        self.local.insert(tick, commands.clone());
+        self.local_hashes.insert(self.tick, state_hash);
+        self.unacked.insert(
+            tick,
+            TickCommands {
+                tick,
+                commands,
+                state_hash,
+            },
+        );
+        self.issued_tick = Some(self.tick);
+    }
+
+    // returns the merged commands of all peers for the current tick, if they are complete
+    fn take_tick_commands(&mut self) -> Option<Vec<GameCommand>> {
+        if self.tick < INPUT_DELAY {
+            return Some(Vec::new());
+        }
+        if !self.local.contains_key(&self.tick) || !self.remote.contains_key(&self.tick) {
+            return None;
+        }
+        let local = self.local.remove(&self.tick).unwrap_or_default();
+        let remote = self.remote.remove(&self.tick).unwrap_or_default();
+
+        // same order on every peer: lower team first
+        if self.local_team == 0 {
+            Some(local.into_iter().chain(remote).collect())
+        } else {
+            Some(remote.into_iter().chain(local).collect())
+        }
+    }
+
+    fn check_hashes(&mut self) -> Vec<DesyncEvent> {
+        let mut desyncs = Vec::new();
+        let ticks: Vec<_> = self
+            .remote_hashes
+            .keys()
+            .filter(|tick| self.local_hashes.contains_key(tick))
+            .cloned()
+            .collect();
+        for tick in ticks {
+            let local_hash = self.local_hashes.remove(&tick).unwrap();
+            let remote_hash = self.remote_hashes.remove(&tick).unwrap();
+            if local_hash != remote_hash {
+                desyncs.push(DesyncEvent {
+                    tick,
+                    local_hash,
+                    remote_hash,
+                });
+            }
+        }
+        desyncs
+    }
+
+    fn send(&self) {
+        let packet = LockstepPacket {
+            received_until: self.remote_next,
+            bundles: self
+                .unacked
+                .values()
+                .take(MAX_BUNDLES_PER_PACKET)
+                .cloned()
+                .collect(),
+        };
+        if let Err(err) = self.transport.send(&packet, self.peer_addr) {
+            debug!("lockstep send failed: {}", err);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn lockstep_system(
+    time: Res<Time>,
+    mut session: ResMut<LockstepSession>,
+    mut game_time: ResMut<GameTime>,
+    turn_state: Res<TurnState>,
+    mut requests: EventReader<CommandRequest>,
+    mut game_commands: EventWriter<GameCommand>,
+    mut desync_events: EventWriter<DesyncEvent>,
+    player_query: Query<(&HexCube, &Player, &Health), Without<PlayerExplosion>>,
+) {
+    session.receive();
+
+    for CommandRequest(command) in requests.iter() {
+        session.pending.push(command.clone());
+    }
+
+    session.timer += time.delta_seconds();
+    if session.timer >= TICK_LENGTH {
+        if session.issued_tick != Some(session.tick) {
+            let hash = state_hash(player_query.iter(), &turn_state);
+            session.issue(hash);
+        }
+
+        // at most one tick per frame, so that the commands of the previous tick are applied before
+        // the state hash for the next one is calculated.
+        if let Some(commands) = session.take_tick_commands() {
+            for command in commands {
+                game_commands.send(command);
+            }
+            session.tick += 1;
+            game_time.advance(TICK_LENGTH);
+            session.timer = (session.timer - TICK_LENGTH).min(TICK_LENGTH);
+        }
+    }
+
+    for desync in session.check_hashes() {
+        error!(
+            "desync detected at tick {}: local {:x} remote {:x}",
+            desync.tick, desync.local_hash, desync.remote_hash
+        );
+        desync_events.send(desync);
+    }
+
+    session.send();
+}
+
+pub struct LockstepPlugin {
+    pub local_addr: SocketAddr,
+    pub peer_addr: SocketAddr,
+    pub local_team: u32,
+}
+
+impl Plugin for LockstepPlugin {
+    fn build(&self, app: &mut App) {
+        let transport =
+            UdpTransport::bind(self.local_addr).expect("failed to bind lockstep socket");
+        app.insert_resource(LockstepSession::new(
+            transport,
+            self.peer_addr,
+            self.local_team,
+        ))
+        .insert_resource(NetworkedCommands)
+        .insert_resource(LocalTeam(self.local_team))
+        .insert_resource(GameTime::fixed_step())
+        .insert_resource(SimTime::locked())
+        .add_event::<DesyncEvent>()
+        .add_system(lockstep_system.before(GameSystem::ApplyCommands));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        combat::DamageEvent,
+        game::{apply_game_commands_system, Tile},
+    };
+
+    #[derive(Default)]
+    struct Desyncs(usize);
+
+    fn count_desyncs_system(mut events: EventReader<DesyncEvent>, mut desyncs: ResMut<Desyncs>) {
+        desyncs.0 += events.iter().count();
+    }
+
+    // same setup as LockstepPlugin, but with a socket bound to a free port
+    fn peer_app(transport: UdpTransport, peer_addr: SocketAddr, local_team: u32) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<TurnState>()
+            .init_resource::<Desyncs>()
+            .add_event::<CommandRequest>()
+            .add_event::<GameCommand>()
+            .add_event::<DamageEvent>()
+            .add_event::<DesyncEvent>()
+            .insert_resource(LockstepSession::new(transport, peer_addr, local_team))
+            .insert_resource(NetworkedCommands)
+            .insert_resource(LocalTeam(local_team))
+            .insert_resource(GameTime::fixed_step())
+            .add_system(lockstep_system.before(GameSystem::ApplyCommands))
+            .add_system(apply_game_commands_system.label(GameSystem::ApplyCommands))
+            .add_system(count_desyncs_system.after(GameSystem::ApplyCommands));
+
+        for x in -2..=2 {
+            app.world
+                .spawn()
+                .insert(HexCube::new(x, -x, 0))
+                .insert(Tile);
+        }
+        for (cube, team) in [(HexCube::new(0, 0, 0), 0), (HexCube::new(2, -2, 0), 1)] {
+            app.world
+                .spawn()
+                .insert(cube)
+                .insert(Player { team })
+                .insert(Health::default())
+                .insert(Transform::default());
+        }
+        app
+    }
+
+    fn app_state_hash(app: &mut App) -> u64 {
+        let turn_state = app.world.get_resource::<TurnState>().unwrap().clone();
+        let mut query = app.world.query::<(&HexCube, &Player, &Health)>();
+        state_hash(query.iter(&app.world), &turn_state)
+    }
+
+    fn app_tick(app: &App) -> u32 {
+        app.world.get_resource::<LockstepSession>().unwrap().tick()
+    }
+
+    #[test]
+    fn two_peers_on_loopback() {
+        let loopback: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let transport0 = UdpTransport::bind(loopback).unwrap();
+        let transport1 = UdpTransport::bind(loopback).unwrap();
+        let addr0 = transport0.local_addr().unwrap();
+        let addr1 = transport1.local_addr().unwrap();
+        let mut apps = [
+            peer_app(transport0, addr1, 0),
+            peer_app(transport1, addr0, 1),
+        ];
+
+        let command = GameCommand::Move {
+            from: HexCube::new(0, 0, 0),
+            to: HexCube::new(-1, 1, 0),
+        };
+        apps[0]
+            .world
+            .get_resource_mut::<Events<CommandRequest>>()
+            .unwrap()
+            .send(CommandRequest(command));
+
+        // ticks advance with real time, the command is executed INPUT_DELAY ticks after it was issued
+        for _ in 0..200 {
+            for app in apps.iter_mut() {
+                app.update();
+            }
+            if apps.iter().all(|app| app_tick(app) > INPUT_DELAY + 2) {
+                break;
+            }
+            std::thread::sleep(Duration::from_secs_f32(TICK_LENGTH / 4.0));
+        }
+
+        for app in apps.iter_mut() {
+            assert!(app_tick(app) > INPUT_DELAY + 2, "lockstep did not advance");
+            let mut query = app.world.query::<(&HexCube, &Player)>();
+            assert!(query
+                .iter(&app.world)
+                .any(|(cube, player)| player.team == 0 && *cube == HexCube::new(-1, 1, 0)));
+            assert_eq!(app.world.get_resource::<Desyncs>().unwrap().0, 0);
+            // the move was the whole turn
+            assert_eq!(
+                app.world.get_resource::<TurnState>().unwrap().active_team,
+                1
+            );
+        }
+        let hashes: Vec<_> = apps.iter_mut().map(app_state_hash).collect();
+        assert_eq!(hashes[0], hashes[1]);
+    }
+}
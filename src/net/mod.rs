@@ -0,0 +1,88 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    combat::Health,
+    game::{Player, TurnState},
+    hex::HexCube,
+};
+
+pub mod client;
+pub mod lockstep;
+pub mod replication;
+pub mod server;
+
+#[cfg(test)]
+mod tests;
+
+// large enough for a few seconds worth of unacknowledged commands
+const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    buf: Vec<u8>,
+}
+
+impl UdpTransport {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpTransport {
+            socket,
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn send<T: Serialize>(&self, msg: &T, addr: SocketAddr) -> io::Result<()> {
+        let data = bincode::serialize(msg).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        self.socket.send_to(&data, addr)?;
+        Ok(())
+    }
+
+    // returns None when no datagram is waiting. Malformed datagrams are logged and dropped.
+    pub fn recv<T: DeserializeOwned>(&mut self) -> Option<(T, SocketAddr)> {
+        loop {
+            match self.socket.recv_from(&mut self.buf) {
+                Ok((len, addr)) => match bincode::deserialize(&self.buf[..len]) {
+                    Ok(msg) => return Some((msg, addr)),
+                    Err(err) => {
+                        bevy::log::warn!("dropping malformed datagram from {}: {}", addr, err)
+                    }
+                },
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return None,
+                Err(err) => {
+                    // e.g. ICMP port unreachable while the peer is not up yet
+                    bevy::log::debug!("recv failed: {}", err);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+// hash of the deterministic (non-physics) game state, used to detect desyncs between peers.
+pub fn state_hash<'a>(
+    players: impl Iterator<Item = (&'a HexCube, &'a Player, &'a Health)>,
+    turn_state: &TurnState,
+) -> u64 {
+    let mut players: Vec<_> = players
+        .map(|(cube, player, health)| (*cube, player.team, health.current.to_bits()))
+        .collect();
+    players.sort_by_key(|(cube, team, _)| (cube.x, cube.y, cube.z, *team));
+
+    let mut hasher = DefaultHasher::new();
+    players.hash(&mut hasher);
+    turn_state.turn.hash(&mut hasher);
+    turn_state.active_team.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,190 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{GameCommand, TurnState},
+    hex::HexCube,
+};
+
+// changes below these thresholds are not replicated
+const TRANSLATION_EPSILON: f32 = 1e-4;
+const ROTATION_EPSILON: f32 = 1e-4;
+
+// network-wide identity of a replicated entity, assigned by the server
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NetId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicatedKind {
+    Tile,
+    Player { team: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplicatedState {
+    pub kind: ReplicatedKind,
+    pub cube: HexCube,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub explosion: Option<f32>,
+}
+
+pub type WorldState = HashMap<NetId, ReplicatedState>;
+
+// only the fields that differ from the baseline are set. kind is only set for entities that are
+// not part of the baseline, in which case all other fields are set as well.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EntityDelta {
+    pub id: u32,
+    pub kind: Option<ReplicatedKind>,
+    pub cube: Option<HexCube>,
+    pub translation: Option<[f32; 3]>,
+    pub rotation: Option<[f32; 4]>,
+    pub explosion: Option<Option<f32>>,
+}
+
+impl ReplicatedState {
+    pub fn delta(&self, id: NetId, baseline: Option<&ReplicatedState>) -> Option<EntityDelta> {
+        let baseline = match baseline {
+            Some(baseline) if baseline.kind == self.kind => baseline,
+            _ => {
+                return Some(EntityDelta {
+                    id: id.0,
+                    kind: Some(self.kind),
+                    cube: Some(self.cube),
+                    translation: Some(self.translation),
+                    rotation: Some(self.rotation),
+                    explosion: Some(self.explosion),
+                })
+            }
+        };
+
+        let mut delta = EntityDelta {
+            id: id.0,
+            ..default()
+        };
+        if self.cube != baseline.cube {
+            delta.cube = Some(self.cube);
+        }
+        if !Vec3::from(self.translation)
+            .abs_diff_eq(baseline.translation.into(), TRANSLATION_EPSILON)
+        {
+            delta.translation = Some(self.translation);
+        }
+        if !Quat::from_array(self.rotation)
+            .abs_diff_eq(Quat::from_array(baseline.rotation), ROTATION_EPSILON)
+        {
+            delta.rotation = Some(self.rotation);
+        }
+        if self.explosion.is_some() != baseline.explosion.is_some() {
+            delta.explosion = Some(self.explosion);
+        }
+
+        if delta.cube.is_none()
+            && delta.translation.is_none()
+            && delta.rotation.is_none()
+            && delta.explosion.is_none()
+        {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+}
+
+impl EntityDelta {
+    pub fn apply(&self, baseline: Option<&ReplicatedState>) -> Option<ReplicatedState> {
+        match (self.kind, baseline) {
+            (Some(kind), _) => Some(ReplicatedState {
+                kind,
+                cube: self.cube?,
+                translation: self.translation?,
+                rotation: self.rotation?,
+                explosion: self.explosion?,
+            }),
+            (None, Some(baseline)) => Some(ReplicatedState {
+                kind: baseline.kind,
+                cube: self.cube.unwrap_or(baseline.cube),
+                translation: self.translation.unwrap_or(baseline.translation),
+                rotation: self.rotation.unwrap_or(baseline.rotation),
+                explosion: self.explosion.unwrap_or(baseline.explosion),
+            }),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: u32,
+    // snapshot id the deltas are relative to. None means the snapshot is complete.
+    pub baseline: Option<u32>,
+    pub turn_state: TurnState,
+    pub entities: Vec<EntityDelta>,
+    pub removed: Vec<u32>,
+}
+
+impl Snapshot {
+    pub fn new(
+        id: u32,
+        current: &WorldState,
+        baseline: Option<(u32, &WorldState)>,
+        turn_state: &TurnState,
+    ) -> Self {
+        let baseline_state = baseline.map(|(_, state)| state);
+        let entities = current
+            .iter()
+            .filter_map(|(id, state)| state.delta(*id, baseline_state.and_then(|b| b.get(id))))
+            .collect();
+        let removed = baseline_state
+            .map(|baseline| {
+                baseline
+                    .keys()
+                    .filter(|id| !current.contains_key(id))
+                    .map(|id| id.0)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Snapshot {
+            id,
+            baseline: baseline.map(|(id, _)| id),
+            turn_state: turn_state.clone(),
+            entities,
+            removed,
+        }
+    }
+
+    // reconstructs the complete world state from the baseline state
+    pub fn apply(&self, baseline: Option<&WorldState>) -> Option<WorldState> {
+        let mut state = match (self.baseline, baseline) {
+            (None, _) => WorldState::default(),
+            (Some(_), Some(baseline)) => baseline.clone(),
+            (Some(_), None) => return None,
+        };
+        for id in &self.removed {
+            state.remove(&NetId(*id));
+        }
+        for delta in &self.entities {
+            let id = NetId(delta.id);
+            let entity_state = delta.apply(state.get(&id))?;
+            state.insert(id, entity_state);
+        }
+        Some(state)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    // token identifies the client across reconnects
+    Join { token: u64 },
+    Intent(GameCommand),
+    Ack { snapshot: u32 },
+    Disconnect,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome { team: Option<u32> },
+    Snapshot(Snapshot),
+}
@@ -0,0 +1,251 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{
+    replication::{
+        ClientMessage, NetId, ReplicatedKind, ReplicatedState, ServerMessage, Snapshot, WorldState,
+    },
+    UdpTransport,
+};
+use crate::{
+    fx::PlayerExplosion,
+    game::{GameCommand, GameSystem, Player, Tile, TurnState},
+    hex::HexCube,
+    sim_time::SimTime,
+};
+
+pub const SNAPSHOT_INTERVAL: f32 = 0.05;
+pub const CLIENT_TIMEOUT: f64 = 5.0;
+
+// number of sent snapshots kept per client as potential delta baselines
+const SNAPSHOT_HISTORY: usize = 64;
+
+struct ConnectedClient {
+    team: Option<u32>,
+    last_seen: f64,
+    acked: Option<u32>,
+    sent: BTreeMap<u32, WorldState>,
+}
+
+pub struct ServerState {
+    transport: UdpTransport,
+    clients: HashMap<SocketAddr, ConnectedClient>,
+    // team reservations survive disconnects, so that clients can rejoin their team
+    teams: HashMap<u64, u32>,
+    num_teams: u32,
+    next_net_id: u32,
+    next_snapshot: u32,
+    timer: f32,
+}
+
+impl ServerState {
+    pub fn new(transport: UdpTransport, num_teams: u32) -> Self {
+        ServerState {
+            transport,
+            clients: HashMap::default(),
+            teams: HashMap::default(),
+            num_teams,
+            next_net_id: 0,
+            next_snapshot: 0,
+            timer: 0.0,
+        }
+    }
+
+    pub fn num_clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.transport.local_addr()
+    }
+
+    fn assign_team(&mut self, token: u64) -> Option<u32> {
+        if let Some(team) = self.teams.get(&token) {
+            return Some(*team);
+        }
+        let team = (0..self.num_teams).find(|team| !self.teams.values().any(|t| t == team))?;
+        self.teams.insert(token, team);
+        Some(team)
+    }
+}
+
+pub fn assign_net_id_system(
+    mut commands: Commands,
+    mut server: ResMut<ServerState>,
+    query: Query<Entity, (Or<(Added<Tile>, Added<Player>)>, Without<NetId>)>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).insert(NetId(server.next_net_id));
+        server.next_net_id += 1;
+    }
+}
+
+pub fn server_receive_system(
+    time: Res<Time>,
+    mut server: ResMut<ServerState>,
+    turn_state: Res<TurnState>,
+    mut game_commands: EventWriter<GameCommand>,
+    player_query: Query<(&HexCube, &Player)>,
+) {
+    let now = time.seconds_since_startup();
+    while let Some((msg, addr)) = server.transport.recv::<ClientMessage>() {
+        match msg {
+            ClientMessage::Join { token } => {
+                let team = server.assign_team(token);
+                info!("client {} joined as team {:?}", addr, team);
+                // a rejoining client starts from a complete snapshot
+                server.clients.insert(
+                    addr,
+                    ConnectedClient {
+                        team,
+                        last_seen: now,
+                        acked: None,
+                        sent: BTreeMap::new(),
+                    },
+                );
+                if let Err(err) = server
+                    .transport
+                    .send(&ServerMessage::Welcome { team }, addr)
+                {
+                    warn!("failed to send welcome to {}: {}", addr, err);
+                }
+            }
+            ClientMessage::Intent(command) => {
+                let team = match server.clients.get_mut(&addr) {
+                    Some(client) => {
+                        client.last_seen = now;
+                        client.team
+                    }
+                    None => continue,
+                };
+                // clients may only command their own team, and only when it is their turn
+                let commanded_team = match &command {
+                    GameCommand::Move { from, .. } | GameCommand::Attack { from, .. } => {
+                        player_query
+                            .iter()
+                            .find(|(cube, _)| *cube == from)
+                            .map(|(_, player)| player.team)
+                    }
+                    GameCommand::EndTurn { team } => Some(*team),
+                };
+                if team.is_some() && team == commanded_team && team == Some(turn_state.active_team)
+                {
+                    game_commands.send(command);
+                } else {
+                    info!("rejected intent from {}: {:?}", addr, command);
+                }
+            }
+            ClientMessage::Ack { snapshot } => {
+                if let Some(client) = server.clients.get_mut(&addr) {
+                    client.last_seen = now;
+                    if client.acked.map_or(true, |acked| snapshot > acked) {
+                        client.acked = Some(snapshot);
+                        // older snapshots are never used as baseline again
+                        client.sent = client.sent.split_off(&snapshot);
+                    }
+                }
+            }
+            ClientMessage::Disconnect => {
+                info!("client {} disconnected", addr);
+                server.clients.remove(&addr);
+            }
+        }
+    }
+
+    let timed_out: Vec<_> = server
+        .clients
+        .iter()
+        .filter(|(_, client)| now - client.last_seen > CLIENT_TIMEOUT)
+        .map(|(addr, _)| *addr)
+        .collect();
+    for addr in timed_out {
+        info!("client {} timed out", addr);
+        server.clients.remove(&addr);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn server_snapshot_system(
+    time: Res<Time>,
+    mut server: ResMut<ServerState>,
+    turn_state: Res<TurnState>,
+    query: Query<(
+        &NetId,
+        &HexCube,
+        &Transform,
+        Option<&Player>,
+        Option<&PlayerExplosion>,
+    )>,
+) {
+    server.timer += time.delta_seconds();
+    if server.timer < SNAPSHOT_INTERVAL {
+        return;
+    }
+    server.timer = 0.0;
+
+    let current: WorldState = query
+        .iter()
+        .map(|(id, cube, transform, player, explosion)| {
+            let kind = match player {
+                Some(player) => ReplicatedKind::Player { team: player.team },
+                None => ReplicatedKind::Tile,
+            };
+            (
+                *id,
+                ReplicatedState {
+                    kind,
+                    cube: *cube,
+                    translation: transform.translation.into(),
+                    rotation: transform.rotation.into(),
+                    explosion: explosion.map(|e| e.time_left),
+                },
+            )
+        })
+        .collect();
+
+    let snapshot_id = server.next_snapshot;
+    server.next_snapshot += 1;
+
+    let server = &mut *server;
+    for (addr, client) in server.clients.iter_mut() {
+        let baseline = client
+            .acked
+            .and_then(|acked| client.sent.get(&acked).map(|state| (acked, state)));
+        let snapshot = Snapshot::new(snapshot_id, &current, baseline, &turn_state);
+
+        if let Err(err) = server
+            .transport
+            .send(&ServerMessage::Snapshot(snapshot), *addr)
+        {
+            warn!("failed to send snapshot to {}: {}", addr, err);
+        }
+
+        client.sent.insert(snapshot_id, current.clone());
+        while client.sent.len() > SNAPSHOT_HISTORY {
+            let oldest = *client.sent.keys().next().unwrap();
+            client.sent.remove(&oldest);
+        }
+    }
+}
+
+// the server owns the board and the players: clients only send intents, which are validated and
+// turned into GameCommands here, and receive delta compressed snapshots of the replicated state.
+pub struct ServerPlugin {
+    pub addr: SocketAddr,
+}
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        let transport = UdpTransport::bind(self.addr).expect("failed to bind server socket");
+        let num_teams = app
+            .world
+            .get_resource::<TurnState>()
+            .map_or(2, |turn_state| turn_state.num_teams);
+        app.insert_resource(ServerState::new(transport, num_teams))
+            .insert_resource(SimTime::locked())
+            .add_system(assign_net_id_system)
+            .add_system(server_receive_system.before(GameSystem::ApplyCommands))
+            .add_system(server_snapshot_system.after(GameSystem::ApplyCommands));
+    }
+}
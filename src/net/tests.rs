@@ -0,0 +1,157 @@
+// server and client apps talking over loopback in one process
+
+use std::{net::SocketAddr, time::Duration};
+
+use bevy::prelude::*;
+
+use super::{
+    client::{ClientPlugin, ClientState, ConnectionStatus},
+    server::{ServerPlugin, ServerState},
+};
+use crate::{
+    combat::{DamageEvent, Health},
+    game::{
+        apply_game_commands_system, CommandRequest, GameCommand, GameSystem, GlobalState,
+        LocalTeam, Player, Tile, TurnState,
+    },
+    hex::HexCube,
+};
+
+const MAX_FRAMES: usize = 500;
+
+fn loopback() -> SocketAddr {
+    "127.0.0.1:0".parse().unwrap()
+}
+
+fn server_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<TurnState>()
+        .add_event::<GameCommand>()
+        .add_event::<DamageEvent>()
+        .add_system(apply_game_commands_system.label(GameSystem::ApplyCommands))
+        .add_plugin(ServerPlugin { addr: loopback() });
+
+    for x in -2..=2 {
+        app.world
+            .spawn()
+            .insert(HexCube::new(x, -x, 0))
+            .insert(Tile)
+            .insert(Transform::default());
+    }
+    for (cube, team) in [(HexCube::new(0, 0, 0), 0), (HexCube::new(2, -2, 0), 1)] {
+        app.world
+            .spawn()
+            .insert(cube)
+            .insert(Player { team })
+            .insert(Health::default())
+            .insert(Transform::default());
+    }
+    app
+}
+
+fn client_app(server_addr: SocketAddr) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<GlobalState>()
+        .init_resource::<TurnState>()
+        .add_event::<CommandRequest>()
+        .add_plugin(ClientPlugin {
+            local_addr: loopback(),
+            server_addr,
+        });
+    app
+}
+
+// updates both apps until the condition holds for the client
+fn run_until(server: &mut App, client: &mut App, condition: impl Fn(&mut App) -> bool) -> bool {
+    for _ in 0..MAX_FRAMES {
+        server.update();
+        client.update();
+        if condition(client) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    false
+}
+
+fn status(app: &App) -> ConnectionStatus {
+    app.world.get_resource::<ClientState>().unwrap().status()
+}
+
+fn local_team(app: &App) -> Option<u32> {
+    app.world.get_resource::<LocalTeam>().map(|team| team.0)
+}
+
+fn players(app: &mut App) -> Vec<(HexCube, u32)> {
+    let mut query = app.world.query::<(&HexCube, &Player)>();
+    let mut players: Vec<_> = query
+        .iter(&app.world)
+        .map(|(cube, player)| (*cube, player.team))
+        .collect();
+    players.sort_by_key(|(_, team)| *team);
+    players
+}
+
+fn num_clients(app: &App) -> usize {
+    app.world
+        .get_resource::<ServerState>()
+        .unwrap()
+        .num_clients()
+}
+
+#[test]
+fn join_move_disconnect_reconnect() {
+    let mut server = server_app();
+    let server_addr = server
+        .world
+        .get_resource::<ServerState>()
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut client = client_app(server_addr);
+
+    // join: the first client gets the first team and a complete snapshot
+    assert!(run_until(&mut server, &mut client, |client| {
+        status(client) == ConnectionStatus::Connected && players(client).len() == 2
+    }));
+    assert_eq!(local_team(&client), Some(0));
+    assert_eq!(num_clients(&server), 1);
+
+    // move: the intent is validated and applied by the server, then replicated
+    let to = HexCube::new(-1, 1, 0);
+    client
+        .world
+        .get_resource_mut::<Events<CommandRequest>>()
+        .unwrap()
+        .send(CommandRequest(GameCommand::Move {
+            from: HexCube::new(0, 0, 0),
+            to,
+        }));
+    assert!(run_until(&mut server, &mut client, |client| {
+        players(client)[0] == (to, 0)
+    }));
+    assert_eq!(players(&mut server)[0], (to, 0));
+
+    // disconnect
+    client
+        .world
+        .get_resource_mut::<ClientState>()
+        .unwrap()
+        .disconnect();
+    for _ in 0..10 {
+        server.update();
+    }
+    assert_eq!(num_clients(&server), 0);
+
+    // reconnect: same team, and the existing entities are reused
+    assert!(run_until(&mut server, &mut client, |client| {
+        status(client) == ConnectionStatus::Connected
+    }));
+    assert_eq!(local_team(&client), Some(0));
+    assert_eq!(num_clients(&server), 1);
+    assert!(run_until(&mut server, &mut client, |client| {
+        players(client) == vec![(to, 0), (HexCube::new(2, -2, 0), 1)]
+    }));
+}
@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    fx::pool::ParticlePool,
+    property::{property_f32, PropertyRegistry, PropertyValue},
+    sim_time::{SimTime, STEP_DURATION},
+};
+
+// numeric properties, stored as strings (see property_f32)
+pub const PHYSICS_GRAVITY_PROPERTY: &str = "physics.gravity";
+// upper limit of the (variable) physics timestep, in seconds
+pub const PHYSICS_MAX_TIMESTEP_PROPERTY: &str = "physics.max_timestep";
+pub const PHYSICS_TIME_SCALE_PROPERTY: &str = "physics.time_scale";
+pub const PHYSICS_SUBSTEPS_PROPERTY: &str = "physics.substeps";
+pub const PHYSICS_SOLVER_ITERATIONS_PROPERTY: &str = "physics.solver_iterations";
+// empty: use the restitution of the effect definitions
+pub const PHYSICS_DEBRIS_RESTITUTION_PROPERTY: &str = "physics.debris_restitution";
+
+// all of the above, in hud order
+pub const PHYSICS_PROPERTIES: [&str; 6] = [
+    PHYSICS_GRAVITY_PROPERTY,
+    PHYSICS_MAX_TIMESTEP_PROPERTY,
+    PHYSICS_TIME_SCALE_PROPERTY,
+    PHYSICS_SUBSTEPS_PROPERTY,
+    PHYSICS_SOLVER_ITERATIONS_PROPERTY,
+    PHYSICS_DEBRIS_RESTITUTION_PROPERTY,
+];
+
+pub fn setup_physics_properties_system(mut commands: Commands) {
+    for (name, value) in [
+        (PHYSICS_GRAVITY_PROPERTY, "-9.81"),
+        (PHYSICS_MAX_TIMESTEP_PROPERTY, "0.016667"),
+        (PHYSICS_TIME_SCALE_PROPERTY, "1.0"),
+        (PHYSICS_SUBSTEPS_PROPERTY, "1"),
+        (PHYSICS_SOLVER_ITERATIONS_PROPERTY, "4"),
+        (PHYSICS_DEBRIS_RESTITUTION_PROPERTY, ""),
+    ] {
+        commands
+            .spawn()
+            .insert(Name::new(name))
+            .insert(PropertyValue::String(value.into()));
+    }
+}
+
+fn is_fixed_step(mode: &TimestepMode, substeps: usize) -> bool {
+    matches!(*mode, TimestepMode::Fixed { dt, substeps: s } if (dt, s) == (STEP_DURATION, substeps))
+}
+
+fn is_variable(mode: &TimestepMode, settings: (f32, f32, usize)) -> bool {
+    matches!(
+        *mode,
+        TimestepMode::Variable { max_dt, time_scale, substeps }
+            if (max_dt, time_scale, substeps) == settings
+    )
+}
+
+// applies the properties to the rapier configuration, values that don't parse are ignored
+pub fn physics_config_system(
+    property_registry: Res<PropertyRegistry>,
+    property_query: Query<&PropertyValue>,
+    mut config: ResMut<RapierConfiguration>,
+    mut context: ResMut<RapierContext>,
+    mut pool: ResMut<ParticlePool>,
+    sim_time: Res<SimTime>,
+    // max_dt, time scale (without the sim time scale) and substeps in effect
+    mut variable_timestep: Local<Option<(f32, f32, usize)>>,
+) {
+    let property = |name| property_f32(&property_registry, &property_query, name);
+
+    if let Some(gravity) = property(PHYSICS_GRAVITY_PROPERTY) {
+        let gravity = Vec3::Y * gravity;
+        if config.gravity != gravity {
+            config.gravity = gravity;
+        }
+    }
+
+    // the settings in effect are kept for properties that don't parse. The sim time scale is
+    // applied on top every frame, so that it is never compounded into the stored time scale.
+    if variable_timestep.is_none() {
+        if let TimestepMode::Variable {
+            max_dt, substeps, ..
+        } = config.timestep_mode
+        {
+            *variable_timestep = Some((max_dt, 1.0, substeps));
+        }
+    }
+    let (current_max_dt, current_time_scale, current_substeps) =
+        variable_timestep.unwrap_or((STEP_DURATION, 1.0, 1));
+    let max_dt = property(PHYSICS_MAX_TIMESTEP_PROPERTY)
+        .filter(|dt| *dt > 0.0)
+        .unwrap_or(current_max_dt);
+    let property_time_scale = property(PHYSICS_TIME_SCALE_PROPERTY)
+        .map(|scale| scale.max(0.0))
+        .unwrap_or(current_time_scale);
+    let substeps = property(PHYSICS_SUBSTEPS_PROPERTY)
+        .map(|substeps| substeps.max(1.0) as usize)
+        .unwrap_or(current_substeps);
+    *variable_timestep = Some((max_dt, property_time_scale, substeps));
+    let time_scale = property_time_scale * sim_time.scale;
+
+    if sim_time.is_stepping() {
+        // a single step advances rapier by exactly the simulated step, whatever the frame time
+        if !is_fixed_step(&config.timestep_mode, substeps) {
+            config.timestep_mode = TimestepMode::Fixed {
+                dt: STEP_DURATION,
+                substeps,
+            };
+        }
+    } else if !is_variable(&config.timestep_mode, (max_dt, time_scale, substeps)) {
+        config.timestep_mode = TimestepMode::Variable {
+            max_dt,
+            time_scale,
+            substeps,
+        };
+    }
+
+    if config.physics_pipeline_active != sim_time.is_running() {
+        config.physics_pipeline_active = sim_time.is_running();
+    }
+
+    if let Some(iterations) = property(PHYSICS_SOLVER_ITERATIONS_PROPERTY) {
+        let iterations = iterations.max(1.0) as usize;
+        if context.integration_parameters.max_velocity_iterations != iterations {
+            context.integration_parameters.max_velocity_iterations = iterations;
+        }
+    }
+
+    let restitution = property(PHYSICS_DEBRIS_RESTITUTION_PROPERTY);
+    if pool.restitution_override != restitution {
+        pool.restitution_override = restitution;
+    }
+}
+
+pub struct PhysicsConfigPlugin;
+
+impl Plugin for PhysicsConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup_physics_properties_system)
+            .add_system(physics_config_system);
+    }
+}
@@ -0,0 +1,264 @@
+use bevy::{prelude::*, render::mesh::PrimitiveTopology};
+use bevy_mod_picking::{HoverEvent, PickingEvent};
+use bevy_rapier3d::{
+    prelude::*,
+    rapier::{
+        geometry::SharedShape,
+        math::{Isometry, Point},
+        parry::shape::TypedShape,
+        pipeline::DebugRenderMode,
+    },
+    render::DebugRenderContext,
+};
+
+use crate::{
+    auto_collider::AutoCollider,
+    property::{property_bool, PropertyRegistry, PropertyValue},
+};
+
+// collider wireframes
+pub const PHYSICS_DEBUG_PROPERTY: &str = "physics.debug_render";
+pub const PHYSICS_DEBUG_CONTACTS_PROPERTY: &str = "physics.debug_contacts";
+// sleeping bodies are drawn darker
+pub const PHYSICS_DEBUG_SLEEPING_PROPERTY: &str = "physics.debug_sleeping";
+// outline the generated collider of the hovered entity
+pub const PHYSICS_DEBUG_HIGHLIGHT_PROPERTY: &str = "physics.debug_highlight";
+
+// hsla multipliers applied to sleeping bodies (when enabled)
+const SLEEP_COLOR_MULTIPLIER: [f32; 4] = [1.0, 1.0, 0.2, 1.0];
+
+// shows the collider of the entity as a line mesh
+#[derive(Component, Default)]
+#[component(storage = "SparseSet")]
+pub struct ColliderHighlight;
+
+#[derive(Component)]
+pub struct ColliderHighlightMesh;
+
+pub fn setup_physics_debug_properties_system(mut commands: Commands) {
+    for name in [
+        PHYSICS_DEBUG_PROPERTY,
+        PHYSICS_DEBUG_CONTACTS_PROPERTY,
+        PHYSICS_DEBUG_SLEEPING_PROPERTY,
+        PHYSICS_DEBUG_HIGHLIGHT_PROPERTY,
+    ] {
+        commands
+            .spawn()
+            .insert(Name::new(name))
+            .insert(PropertyValue::Bool(false));
+    }
+}
+
+pub fn physics_debug_property_system(
+    property_registry: Res<PropertyRegistry>,
+    property_query: Query<&PropertyValue>,
+    mut debug_context: ResMut<DebugRenderContext>,
+) {
+    let enabled =
+        property_bool(&property_registry, &property_query, PHYSICS_DEBUG_PROPERTY).unwrap_or(false);
+    let contacts = property_bool(
+        &property_registry,
+        &property_query,
+        PHYSICS_DEBUG_CONTACTS_PROPERTY,
+    )
+    .unwrap_or(false);
+    let sleeping = property_bool(
+        &property_registry,
+        &property_query,
+        PHYSICS_DEBUG_SLEEPING_PROPERTY,
+    )
+    .unwrap_or(false);
+
+    let mut mode = DebugRenderMode::COLLIDER_SHAPES;
+    if contacts {
+        mode |= DebugRenderMode::SOLVER_CONTACTS;
+    }
+    let sleep_color_multiplier = if sleeping {
+        SLEEP_COLOR_MULTIPLIER
+    } else {
+        [1.0; 4]
+    };
+
+    // avoid triggering change detection every frame
+    if debug_context.enabled != enabled {
+        debug_context.enabled = enabled;
+    }
+    if debug_context.pipeline.mode != mode {
+        debug_context.pipeline.mode = mode;
+    }
+    if debug_context.pipeline.style.sleep_color_multiplier != sleep_color_multiplier {
+        debug_context.pipeline.style.sleep_color_multiplier = sleep_color_multiplier;
+    }
+}
+
+fn remove_highlight(
+    commands: &mut Commands,
+    entity: Entity,
+    children_query: &Query<&Children>,
+    highlight_mesh_query: &Query<(), With<ColliderHighlightMesh>>,
+) {
+    commands.entity(entity).remove::<ColliderHighlight>();
+    if let Ok(children) = children_query.get(entity) {
+        for child in children.iter() {
+            if highlight_mesh_query.contains(*child) {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn collider_highlight_hover_system(
+    mut commands: Commands,
+    mut events: EventReader<PickingEvent>,
+    property_registry: Res<PropertyRegistry>,
+    property_query: Query<&PropertyValue>,
+    auto_query: Query<(), With<AutoCollider>>,
+    highlighted_query: Query<Entity, With<ColliderHighlight>>,
+    children_query: Query<&Children>,
+    highlight_mesh_query: Query<(), With<ColliderHighlightMesh>>,
+) {
+    let enabled = property_bool(
+        &property_registry,
+        &property_query,
+        PHYSICS_DEBUG_HIGHLIGHT_PROPERTY,
+    )
+    .unwrap_or(false);
+    if !enabled {
+        for entity in highlighted_query.iter() {
+            remove_highlight(
+                &mut commands,
+                entity,
+                &children_query,
+                &highlight_mesh_query,
+            );
+        }
+    }
+    for event in events.iter() {
+        match event {
+            PickingEvent::Hover(HoverEvent::JustEntered(entity))
+                if enabled && auto_query.contains(*entity) =>
+            {
+                commands.entity(*entity).insert(ColliderHighlight);
+            }
+            PickingEvent::Hover(HoverEvent::JustLeft(entity))
+                if highlighted_query.contains(*entity) =>
+            {
+                remove_highlight(
+                    &mut commands,
+                    *entity,
+                    &children_query,
+                    &highlight_mesh_query,
+                );
+            }
+            _ => (),
+        }
+    }
+}
+
+fn to_vec3(p: &Point<f32>) -> Vec3 {
+    Vec3::new(p.x, p.y, p.z)
+}
+
+fn isometry_matrix(iso: &Isometry<f32>) -> Mat4 {
+    let t = iso.translation.vector;
+    let r = iso.rotation;
+    Mat4::from_rotation_translation(
+        Quat::from_xyzw(r.i, r.j, r.k, r.w),
+        Vec3::new(t.x, t.y, t.z),
+    )
+}
+
+fn triangle_lines(
+    matrix: Mat4,
+    vertices: &[Point<f32>],
+    indices: &[[u32; 3]],
+    out: &mut Vec<Vec3>,
+) {
+    let point = |i: u32| matrix.transform_point3(to_vec3(&vertices[i as usize]));
+    for [a, b, c] in indices {
+        let (a, b, c) = (point(*a), point(*b), point(*c));
+        out.extend([a, b, b, c, c, a]);
+    }
+}
+
+// edges of the triangles of all (supported) shapes in the collider, as line list
+fn collider_lines(shape: &SharedShape, matrix: Mat4, out: &mut Vec<Vec3>) {
+    match shape.as_typed_shape() {
+        TypedShape::TriMesh(trimesh) => {
+            triangle_lines(matrix, trimesh.vertices(), trimesh.indices(), out)
+        }
+        TypedShape::ConvexPolyhedron(polyhedron) => {
+            let (vertices, indices) = polyhedron.to_trimesh();
+            triangle_lines(matrix, &vertices, &indices, out);
+        }
+        TypedShape::Cuboid(cuboid) => {
+            let (vertices, indices) = cuboid.to_trimesh();
+            triangle_lines(matrix, &vertices, &indices, out);
+        }
+        TypedShape::Compound(compound) => {
+            for (iso, shape) in compound.shapes() {
+                collider_lines(shape, matrix * isometry_matrix(iso), out);
+            }
+        }
+        _ => (),
+    }
+}
+
+pub fn collider_highlight_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut material: Local<Option<Handle<StandardMaterial>>>,
+    added_query: Query<(Entity, &Collider), Added<ColliderHighlight>>,
+) {
+    for (entity, collider) in added_query.iter() {
+        let mut lines = Vec::new();
+        collider_lines(&collider.raw, Mat4::IDENTITY, &mut lines);
+        if lines.is_empty() {
+            continue;
+        }
+        let positions: Vec<[f32; 3]> = lines.iter().map(|p| p.to_array()).collect();
+        // the pbr pipeline expects normals and uvs, even for unlit lines
+        let normals = vec![[0.0, 1.0, 0.0]; positions.len()];
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+
+        let material = material
+            .get_or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color: Color::YELLOW,
+                    unlit: true,
+                    ..default()
+                })
+            })
+            .clone();
+        commands.entity(entity).with_children(|commands| {
+            commands
+                .spawn_bundle(PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material,
+                    // slightly enlarged, so that the lines are not hidden by the mesh itself
+                    transform: Transform::from_scale(Vec3::splat(1.01)),
+                    ..default()
+                })
+                .insert(ColliderHighlightMesh);
+        });
+    }
+}
+
+pub struct PhysicsDebugPlugin;
+
+impl Plugin for PhysicsDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(RapierDebugRenderPlugin::default())
+            .add_startup_system(setup_physics_debug_properties_system)
+            .add_system(physics_debug_property_system)
+            // picking events are sent late in the frame
+            .add_system_to_stage(CoreStage::PostUpdate, collider_highlight_hover_system)
+            .add_system(collider_highlight_system);
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    combat::{damage_system, DamageEvent},
+    destruction::DestroyedTile,
+    game::{NetworkedCommands, Player, Tile},
+    hex::{world_to_cube, HexCube},
+};
+
+// loose tiles hitting a player faster than this cause damage
+pub const FALLING_TILE_DAMAGE_SPEED: f32 = 2.0;
+pub const FALLING_TILE_DAMAGE: f32 = 20.0;
+
+// a player came to rest on (or bounced off) a tile
+#[derive(Clone, Debug)]
+pub struct PlayerLanded {
+    pub player: Entity,
+    pub tile: Entity,
+    pub cube: HexCube,
+}
+
+// Debris (any dynamic body that is neither a player nor part of the board, e.g. loose tiles or
+// particles) hit a player. speed is the debris speed at the time of the contact, if known.
+#[derive(Clone, Debug)]
+pub struct DebrisHitPlayer {
+    pub debris: Entity,
+    pub player: Entity,
+    pub cube: HexCube,
+    pub speed: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct DebrisHitTile {
+    pub debris: Entity,
+    pub tile: Entity,
+    pub cube: HexCube,
+}
+
+enum Body {
+    Player(HexCube),
+    Tile(HexCube),
+    Debris(f32),
+}
+
+// rapier only reports contacts of colliders with active collision events, the board itself is
+// covered by the players and loose tiles touching it.
+#[allow(clippy::type_complexity)]
+pub fn enable_collision_events_system(
+    mut commands: Commands,
+    query: Query<Entity, Or<(Added<Player>, Added<DestroyedTile>)>>,
+) {
+    for entity in query.iter() {
+        commands
+            .entity(entity)
+            .insert(ActiveEvents::COLLISION_EVENTS)
+            // written back by rapier, used for the impact speed
+            .insert(Velocity::default());
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn collision_events_system(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut landed_events: EventWriter<PlayerLanded>,
+    mut debris_player_events: EventWriter<DebrisHitPlayer>,
+    mut debris_tile_events: EventWriter<DebrisHitTile>,
+    query: Query<(
+        Option<&HexCube>,
+        Option<&Player>,
+        Option<&Tile>,
+        Option<&RigidBody>,
+        Option<&Velocity>,
+        &GlobalTransform,
+    )>,
+) {
+    let classify = |entity: Entity| {
+        let (cube, player, tile, body, velocity, transform) = query.get(entity).ok()?;
+        let cube = cube
+            .cloned()
+            .unwrap_or_else(|| world_to_cube(transform.translation));
+        match (player, tile, body) {
+            (Some(_), _, _) => Some(Body::Player(cube)),
+            (None, Some(_), _) => Some(Body::Tile(cube)),
+            (None, None, Some(RigidBody::Dynamic)) => Some(Body::Debris(
+                velocity.map_or(0.0, |velocity| velocity.linvel.length()),
+            )),
+            _ => None,
+        }
+    };
+
+    for event in collision_events.iter() {
+        let (e1, e2) = match event {
+            CollisionEvent::Started(e1, e2, _) => (*e1, *e2),
+            CollisionEvent::Stopped(..) => continue,
+        };
+        let (b1, b2) = match (classify(e1), classify(e2)) {
+            (Some(b1), Some(b2)) => (b1, b2),
+            _ => continue,
+        };
+        // handle both orders of the pair
+        for ((a, body_a), (b, body_b)) in [((e1, &b1), (e2, &b2)), ((e2, &b2), (e1, &b1))] {
+            match (body_a, body_b) {
+                (Body::Player(_), Body::Tile(cube)) => landed_events.send(PlayerLanded {
+                    player: a,
+                    tile: b,
+                    cube: *cube,
+                }),
+                (Body::Debris(speed), Body::Player(cube)) => {
+                    debris_player_events.send(DebrisHitPlayer {
+                        debris: a,
+                        player: b,
+                        cube: *cube,
+                        speed: *speed,
+                    })
+                }
+                (Body::Debris(_), Body::Tile(cube)) => debris_tile_events.send(DebrisHitTile {
+                    debris: a,
+                    tile: b,
+                    cube: *cube,
+                }),
+                _ => (),
+            }
+        }
+    }
+}
+
+// Physics is not deterministic across peers, so under lockstep (and on replicated clients, which
+// don't own the health of players) falling tiles don't cause damage.
+pub fn falling_tile_damage_system(
+    networked: Option<Res<NetworkedCommands>>,
+    mut events: EventReader<DebrisHitPlayer>,
+    mut damage_events: EventWriter<DamageEvent>,
+    destroyed_query: Query<(), With<DestroyedTile>>,
+) {
+    for event in events.iter() {
+        if networked.is_some() {
+            continue;
+        }
+        if event.speed > FALLING_TILE_DAMAGE_SPEED && destroyed_query.contains(event.debris) {
+            damage_events.send(DamageEvent {
+                target: event.player,
+                source: None,
+                amount: FALLING_TILE_DAMAGE,
+            });
+        }
+    }
+}
+
+pub struct PhysicsEventsPlugin;
+
+impl Plugin for PhysicsEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayerLanded>()
+            .add_event::<DebrisHitPlayer>()
+            .add_event::<DebrisHitTile>()
+            .add_system(enable_collision_events_system)
+            .add_system(collision_events_system)
+            .add_system(
+                falling_tile_damage_system
+                    .after(collision_events_system)
+                    .before(damage_system),
+            );
+    }
+}
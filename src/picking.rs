@@ -0,0 +1,142 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_mod_picking::{HoverEvent, PickingEvent};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    game::Tile,
+    hex::{world_to_cube, HexCube},
+};
+
+const MAX_PICK_DISTANCE: f32 = 100.0;
+
+// camera used for casting the cursor ray
+#[derive(Component, Default)]
+pub struct PhysicsPickingCamera;
+
+// cell -> tile entity, for hits on colliders that do not belong to a single tile
+#[derive(Default)]
+pub struct TileIndex {
+    tiles: HashMap<HexCube, Entity>,
+    cells: HashMap<Entity, HexCube>,
+}
+
+impl TileIndex {
+    pub fn get(&self, cube: &HexCube) -> Option<Entity> {
+        self.tiles.get(cube).copied()
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(cube) = self.cells.remove(&entity) {
+            if self.tiles.get(&cube) == Some(&entity) {
+                self.tiles.remove(&cube);
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn tile_index_system(
+    mut index: ResMut<TileIndex>,
+    removed: RemovedComponents<Tile>,
+    query: Query<(Entity, &HexCube), (With<Tile>, Or<(Added<Tile>, Changed<HexCube>)>)>,
+) {
+    for entity in removed.iter() {
+        index.remove(entity);
+    }
+    for (entity, cube) in query.iter() {
+        // the tile may have moved to another cell
+        index.remove(entity);
+        index.tiles.insert(*cube, entity);
+        index.cells.insert(entity, *cube);
+    }
+}
+
+// ray through the cursor position, in world space
+fn cursor_ray(
+    cursor: Vec2,
+    window: &Window,
+    camera: &Camera,
+    transform: &GlobalTransform,
+) -> (Vec3, Vec3) {
+    let size = Vec2::new(window.width(), window.height());
+    let ndc = cursor / size * 2.0 - Vec2::ONE;
+    let ndc_to_world = transform.compute_matrix() * camera.projection_matrix.inverse();
+    // reversed infinite z: the near plane is at 1, infinity at 0
+    let near = ndc_to_world.project_point3(ndc.extend(1.0));
+    let far = ndc_to_world.project_point3(ndc.extend(f32::EPSILON));
+    (near, (far - near).normalize())
+}
+
+// Alternative to the bevy_mod_picking raycaster: casts the cursor ray into the Rapier world (tiles
+// get their colliders from AutoColliderPlugin) and sends the same PickingEvents for the tile hit.
+#[allow(clippy::too_many_arguments)]
+pub fn physics_picking_system(
+    windows: Res<Windows>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    rapier_context: Res<RapierContext>,
+    tile_index: Res<TileIndex>,
+    mut hovered: Local<Option<Entity>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<PhysicsPickingCamera>>,
+    tile_query: Query<(), With<Tile>>,
+    body_query: Query<&RigidBody>,
+    mut picking_events: EventWriter<PickingEvent>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let (camera, camera_transform) = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+
+    let hit = window.cursor_position().and_then(|cursor| {
+        let (origin, dir) = cursor_ray(cursor, window, camera, camera_transform);
+        // players, debris and loose tiles (all dynamic) would otherwise hide the cell below
+        let pickable = |entity: Entity| {
+            body_query
+                .get(entity)
+                .map_or(true, |body| *body != RigidBody::Dynamic)
+        };
+        let (entity, toi) = rapier_context.cast_ray(
+            origin,
+            dir,
+            MAX_PICK_DISTANCE,
+            true,
+            InteractionGroups::all(),
+            Some(&pickable),
+        )?;
+        // colliders shared by a merged board do not map to a single tile, go by the hit position
+        match tile_query.get(entity) {
+            Ok(()) => Some(entity),
+            Err(_) => tile_index.get(&world_to_cube(origin + dir * toi)),
+        }
+    });
+
+    if hit != *hovered {
+        if let Some(entity) = *hovered {
+            picking_events.send(PickingEvent::Hover(HoverEvent::JustLeft(entity)));
+        }
+        if let Some(entity) = hit {
+            picking_events.send(PickingEvent::Hover(HoverEvent::JustEntered(entity)));
+        }
+        *hovered = hit;
+    }
+
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        if let Some(entity) = hit {
+            picking_events.send(PickingEvent::Clicked(entity));
+        }
+    }
+}
+
+pub struct PhysicsPickingPlugin;
+
+impl Plugin for PhysicsPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PickingEvent>()
+            .init_resource::<TileIndex>()
+            .add_system(tile_index_system)
+            .add_system(physics_picking_system.after(tile_index_system));
+    }
+}
@@ -1 +1,26 @@
 pub use bevy_ecs_property::*;
+
+use bevy::prelude::*;
+
+// numeric properties are stored as strings, so that they can be edited in the hud
+pub fn property_f32(
+    registry: &PropertyRegistry,
+    query: &Query<&PropertyValue>,
+    name: &str,
+) -> Option<f32> {
+    match query.get(registry.get(name)?).ok()? {
+        PropertyValue::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn property_bool(
+    registry: &PropertyRegistry,
+    query: &Query<&PropertyValue>,
+    name: &str,
+) -> Option<bool> {
+    match query.get(registry.get(name)?).ok()? {
+        PropertyValue::Bool(v) => Some(*v),
+        _ => None,
+    }
+}
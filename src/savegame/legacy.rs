@@ -0,0 +1,148 @@
+// Layouts of older savegame versions, only used for reading and converting them to the current
+// SaveGame (see SaveGameMigrations).
+
+use serde::Deserialize;
+
+use super::{SaveGame, SavedMaterial, SavedPlayer, SavedProperty, SavedTile};
+use crate::{game::TurnState, hex::HexCube};
+
+// version 1 to 3: tiles without scorch
+#[derive(Clone, Debug, Deserialize)]
+pub struct SavedTileV1 {
+    pub cube: HexCube,
+    pub height: f32,
+    pub material: usize,
+}
+
+// version 1: players without team
+#[derive(Clone, Debug, Deserialize)]
+pub struct SavedPlayerV1 {
+    pub cube: HexCube,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub explosion_time_left: Option<f32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SaveGameV1 {
+    pub version: u32,
+    pub materials: Vec<SavedMaterial>,
+    pub tiles: Vec<SavedTileV1>,
+    pub players: Vec<SavedPlayerV1>,
+    pub turn_state: TurnState,
+    pub properties: Vec<SavedProperty>,
+}
+
+impl SaveGameV1 {
+    pub fn upgrade(self) -> SaveGameV2 {
+        SaveGameV2 {
+            version: 2,
+            materials: self.materials,
+            tiles: self.tiles,
+            players: self
+                .players
+                .into_iter()
+                .map(|player| SavedPlayerV2 {
+                    cube: player.cube,
+                    // all players were on the same team
+                    team: 0,
+                    translation: player.translation,
+                    rotation: player.rotation,
+                    explosion_time_left: player.explosion_time_left,
+                })
+                .collect(),
+            turn_state: self.turn_state,
+            properties: self.properties,
+        }
+    }
+}
+
+impl From<SaveGameV1> for SaveGame {
+    fn from(savegame: SaveGameV1) -> Self {
+        savegame.upgrade().into()
+    }
+}
+
+// version 2: players without health
+#[derive(Clone, Debug, Deserialize)]
+pub struct SavedPlayerV2 {
+    pub cube: HexCube,
+    pub team: u32,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub explosion_time_left: Option<f32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SaveGameV2 {
+    pub version: u32,
+    pub materials: Vec<SavedMaterial>,
+    pub tiles: Vec<SavedTileV1>,
+    pub players: Vec<SavedPlayerV2>,
+    pub turn_state: TurnState,
+    pub properties: Vec<SavedProperty>,
+}
+
+impl SaveGameV2 {
+    pub fn upgrade(self) -> SaveGameV3 {
+        SaveGameV3 {
+            version: 3,
+            materials: self.materials,
+            tiles: self.tiles,
+            players: self
+                .players
+                .into_iter()
+                .map(|player| SavedPlayer {
+                    cube: player.cube,
+                    team: player.team,
+                    // spawned with full health
+                    health: None,
+                    translation: player.translation,
+                    rotation: player.rotation,
+                    explosion_time_left: player.explosion_time_left,
+                })
+                .collect(),
+            turn_state: self.turn_state,
+            properties: self.properties,
+        }
+    }
+}
+
+impl From<SaveGameV2> for SaveGame {
+    fn from(savegame: SaveGameV2) -> Self {
+        savegame.upgrade().into()
+    }
+}
+
+// version 3: see SavedTileV1
+#[derive(Clone, Debug, Deserialize)]
+pub struct SaveGameV3 {
+    pub version: u32,
+    pub materials: Vec<SavedMaterial>,
+    pub tiles: Vec<SavedTileV1>,
+    pub players: Vec<SavedPlayer>,
+    pub turn_state: TurnState,
+    pub properties: Vec<SavedProperty>,
+}
+
+impl From<SaveGameV3> for SaveGame {
+    fn from(savegame: SaveGameV3) -> Self {
+        SaveGame {
+            version: super::SAVEGAME_VERSION,
+            materials: savegame.materials,
+            tiles: savegame
+                .tiles
+                .into_iter()
+                .map(|tile| SavedTile {
+                    cube: tile.cube,
+                    height: tile.height,
+                    material: tile.material,
+                    scorch: 0.0,
+                })
+                .collect(),
+            players: savegame.players,
+            turn_state: savegame.turn_state,
+            properties: savegame.properties,
+        }
+    }
+}
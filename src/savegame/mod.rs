@@ -0,0 +1,681 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    combat::Health,
+    fx::{scorch::Scorch, PlayerExplosion},
+    game::{spawn_player, spawn_tile, GlobalState, Player, Tile, TurnState},
+    hex::HexCube,
+    property::{PropertyRegistry, PropertyUpdateEvent, PropertyValue},
+};
+
+pub mod legacy;
+
+// bump this whenever the layout of SaveGame changes and register a migration from the
+// previous version in SaveGameMigrations.
+pub const SAVEGAME_VERSION: u32 = 4;
+
+#[derive(Debug)]
+pub enum SaveGameError {
+    Io(std::io::Error),
+    Format(String),
+    MissingVersion,
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SaveGameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveGameError::Io(err) => write!(f, "io error: {}", err),
+            SaveGameError::Format(err) => write!(f, "format error: {}", err),
+            SaveGameError::MissingVersion => write!(f, "savegame has no version field"),
+            SaveGameError::UnsupportedVersion(version) => {
+                write!(f, "unsupported savegame version: {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SaveGameError {}
+
+impl From<std::io::Error> for SaveGameError {
+    fn from(err: std::io::Error) -> Self {
+        SaveGameError::Io(err)
+    }
+}
+
+impl From<ron::Error> for SaveGameError {
+    fn from(err: ron::Error) -> Self {
+        SaveGameError::Format(err.to_string())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedMaterial {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 4],
+    pub metallic: f32,
+    pub perceptual_roughness: f32,
+    pub reflectance: f32,
+}
+
+impl From<&StandardMaterial> for SavedMaterial {
+    fn from(material: &StandardMaterial) -> Self {
+        SavedMaterial {
+            base_color: material.base_color.as_rgba_f32(),
+            emissive: material.emissive.as_rgba_f32(),
+            metallic: material.metallic,
+            perceptual_roughness: material.perceptual_roughness,
+            reflectance: material.reflectance,
+        }
+    }
+}
+
+impl From<&SavedMaterial> for StandardMaterial {
+    fn from(material: &SavedMaterial) -> Self {
+        let [r, g, b, a] = material.base_color;
+        let [er, eg, eb, ea] = material.emissive;
+        StandardMaterial {
+            base_color: Color::rgba(r, g, b, a),
+            emissive: Color::rgba(er, eg, eb, ea),
+            metallic: material.metallic,
+            perceptual_roughness: material.perceptual_roughness,
+            reflectance: material.reflectance,
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedTile {
+    pub cube: HexCube,
+    pub height: f32,
+    // index of the unscorched material
+    pub material: usize,
+    pub scorch: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub cube: HexCube,
+    pub team: u32,
+    // None: full health
+    pub health: Option<Health>,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub explosion_time_left: Option<f32>,
+}
+
+// mirror of the PropertyValue variants that can be persisted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SavedPropertyValue {
+    Bool(bool),
+    String(String),
+    Color([f32; 3]),
+}
+
+impl SavedPropertyValue {
+    fn from_property(value: &PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::Bool(v) => Some(SavedPropertyValue::Bool(*v)),
+            PropertyValue::String(s) => Some(SavedPropertyValue::String(s.clone())),
+            PropertyValue::Color(c) => Some(SavedPropertyValue::Color([c.x, c.y, c.z])),
+            _ => None,
+        }
+    }
+
+    fn to_property(&self) -> PropertyValue {
+        match self {
+            SavedPropertyValue::Bool(v) => PropertyValue::Bool(*v),
+            SavedPropertyValue::String(s) => PropertyValue::String(s.clone()),
+            SavedPropertyValue::Color(c) => PropertyValue::Color((*c).into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedProperty {
+    pub name: String,
+    pub value: SavedPropertyValue,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub materials: Vec<SavedMaterial>,
+    pub tiles: Vec<SavedTile>,
+    pub players: Vec<SavedPlayer>,
+    pub turn_state: TurnState,
+    pub properties: Vec<SavedProperty>,
+}
+
+impl SaveGame {
+    pub fn write(&self, path: &Path) -> Result<(), SaveGameError> {
+        let s = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, s)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path, migrations: &SaveGameMigrations) -> Result<SaveGame, SaveGameError> {
+        let s = std::fs::read_to_string(path)?;
+        // only the version is read up front, the rest is parsed with the layout of that version
+        let header: SaveGameHeader =
+            ron::from_str(&s).map_err(|_| SaveGameError::MissingVersion)?;
+        if header.version == SAVEGAME_VERSION {
+            return Ok(ron::from_str(&s)?);
+        }
+        match migrations.migrations.get(&header.version) {
+            Some(migration) if header.version < SAVEGAME_VERSION => {
+                info!("migrating savegame from version {}", header.version);
+                migration(&s)
+            }
+            _ => Err(SaveGameError::UnsupportedVersion(header.version)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveGameHeader {
+    version: u32,
+}
+
+// Reads a savegame of an older version and converts it to the current SaveGame. Keep the layout
+// of the old version as its own type and implement the conversion on that, see migrate.
+pub type MigrationFn = fn(&str) -> Result<SaveGame, SaveGameError>;
+
+// migration for old layouts that convert to the current one
+pub fn migrate<T: DeserializeOwned + Into<SaveGame>>(s: &str) -> Result<SaveGame, SaveGameError> {
+    let old: T = ron::from_str(s)?;
+    Ok(old.into())
+}
+
+pub struct SaveGameMigrations {
+    migrations: HashMap<u32, MigrationFn>,
+}
+
+impl Default for SaveGameMigrations {
+    fn default() -> Self {
+        let mut migrations = SaveGameMigrations {
+            migrations: HashMap::default(),
+        };
+        migrations
+            .add(1, migrate::<legacy::SaveGameV1>)
+            .add(2, migrate::<legacy::SaveGameV2>)
+            .add(3, migrate::<legacy::SaveGameV3>);
+        migrations
+    }
+}
+
+impl SaveGameMigrations {
+    pub fn add(&mut self, from_version: u32, migration: MigrationFn) -> &mut Self {
+        self.migrations.insert(from_version, migration);
+        self
+    }
+}
+
+pub struct SaveGameEvent(pub PathBuf);
+pub struct LoadGameEvent(pub PathBuf);
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn save_game_system(
+    mut events: EventReader<SaveGameEvent>,
+    turn_state: Res<TurnState>,
+    materials: Res<Assets<StandardMaterial>>,
+    property_registry: Res<PropertyRegistry>,
+    tile_query: Query<
+        (
+            &HexCube,
+            &Transform,
+            &Handle<StandardMaterial>,
+            Option<&Scorch>,
+        ),
+        With<Tile>,
+    >,
+    player_query: Query<(
+        &HexCube,
+        &Player,
+        &Health,
+        &Transform,
+        Option<&PlayerExplosion>,
+    )>,
+    property_query: Query<(&Name, &PropertyValue)>,
+) {
+    for SaveGameEvent(path) in events.iter() {
+        let mut material_indices = HashMap::default();
+        let mut saved_materials = Vec::new();
+        let mut tiles = Vec::new();
+        for (cube, transform, material, scorch) in tile_query.iter() {
+            // scorched variants are recreated from the base material on load
+            let material = scorch
+                .and_then(|scorch| scorch.base_material())
+                .unwrap_or(material);
+            let material = *material_indices.entry(material.clone()).or_insert_with(|| {
+                saved_materials.push(
+                    materials
+                        .get(material)
+                        .map(SavedMaterial::from)
+                        .unwrap_or_else(|| (&StandardMaterial::default()).into()),
+                );
+                saved_materials.len() - 1
+            });
+            tiles.push(SavedTile {
+                cube: *cube,
+                height: transform.translation.y,
+                material,
+                scorch: scorch.map_or(0.0, |scorch| scorch.amount),
+            });
+        }
+
+        let players = player_query
+            .iter()
+            .map(|(cube, player, health, transform, explosion)| SavedPlayer {
+                cube: *cube,
+                team: player.team,
+                health: Some(*health),
+                translation: transform.translation.into(),
+                rotation: transform.rotation.into(),
+                explosion_time_left: explosion.map(|e| e.time_left),
+            })
+            .collect();
+
+        let properties = property_query
+            .iter()
+            .filter(|(name, _)| property_registry.get(name.as_str()).is_some())
+            .filter_map(|(name, value)| {
+                Some(SavedProperty {
+                    name: name.to_string(),
+                    value: SavedPropertyValue::from_property(value)?,
+                })
+            })
+            .collect();
+
+        let savegame = SaveGame {
+            version: SAVEGAME_VERSION,
+            materials: saved_materials,
+            tiles,
+            players,
+            turn_state: turn_state.clone(),
+            properties,
+        };
+
+        match savegame.write(path) {
+            Ok(()) => info!("saved game to {:?}", path),
+            Err(err) => error!("failed to save game to {:?}: {}", path, err),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn load_game_system(
+    mut commands: Commands,
+    mut events: EventReader<LoadGameEvent>,
+    migrations: Res<SaveGameMigrations>,
+    mut global_state: ResMut<GlobalState>,
+    mut turn_state: ResMut<TurnState>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut property_update_events: EventWriter<PropertyUpdateEvent>,
+    despawn_query: Query<Entity, Or<(With<Tile>, With<Player>)>>,
+) {
+    for LoadGameEvent(path) in events.iter() {
+        let savegame = match SaveGame::read(path, &migrations) {
+            Ok(savegame) => savegame,
+            Err(err) => {
+                error!("failed to load game from {:?}: {}", path, err);
+                continue;
+            }
+        };
+
+        for entity in despawn_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let tile_materials: Vec<_> = savegame
+            .materials
+            .iter()
+            .map(|material| materials.add(material.into()))
+            .collect();
+        if let Some(material) = tile_materials.first() {
+            global_state.tile_material = material.clone();
+        }
+
+        for tile in &savegame.tiles {
+            let material = tile_materials
+                .get(tile.material)
+                .cloned()
+                .unwrap_or_else(|| global_state.tile_material.clone());
+            let entity = spawn_tile(
+                &mut commands,
+                &global_state,
+                tile.cube,
+                tile.height,
+                material,
+            );
+            if tile.scorch > 0.0 {
+                commands.entity(entity).insert(Scorch::new(tile.scorch));
+            }
+        }
+
+        for player in &savegame.players {
+            let entity = spawn_player(&mut commands, &global_state, player.cube, player.team);
+            let mut ec = commands.entity(entity);
+            ec.insert(Transform {
+                translation: player.translation.into(),
+                rotation: Quat::from_array(player.rotation),
+                ..default()
+            });
+            if let Some(health) = player.health {
+                ec.insert(health);
+            }
+            if let Some(time_left) = player.explosion_time_left {
+                ec.insert(PlayerExplosion { time_left });
+            }
+        }
+
+        *turn_state = savegame.turn_state.clone();
+
+        for property in &savegame.properties {
+            property_update_events.send(PropertyUpdateEvent::new(
+                property.name.clone(),
+                property.value.to_property(),
+            ));
+        }
+        info!(
+            "loaded game from {:?}: {} tiles, {} players",
+            path,
+            savegame.tiles.len(),
+            savegame.players.len()
+        );
+    }
+}
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveGameEvent>()
+            .add_event::<LoadGameEvent>()
+            .init_resource::<SaveGameMigrations>()
+            .add_system(save_game_system)
+            .add_system(load_game_system);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics_config::{setup_physics_properties_system, PHYSICS_GRAVITY_PROPERTY};
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("game2_{}_{}.ron", name, std::process::id()))
+    }
+
+    fn test_savegame() -> SaveGame {
+        SaveGame {
+            version: SAVEGAME_VERSION,
+            materials: vec![(&StandardMaterial::default()).into()],
+            tiles: vec![SavedTile {
+                cube: HexCube::new(1, -1, 0),
+                height: 0.5,
+                material: 0,
+                scorch: 0.25,
+            }],
+            players: vec![SavedPlayer {
+                cube: HexCube::new(1, -1, 0),
+                team: 1,
+                health: Some(Health {
+                    current: 30.0,
+                    max: 100.0,
+                }),
+                translation: [1.0, 0.5, 0.0],
+                rotation: [0.0, 0.0, 0.0, 1.0],
+                explosion_time_left: None,
+            }],
+            turn_state: TurnState {
+                turn: 3,
+                active_team: 1,
+                num_teams: 2,
+            },
+            properties: vec![
+                SavedProperty {
+                    name: "test.bool".into(),
+                    value: SavedPropertyValue::Bool(true),
+                },
+                SavedProperty {
+                    name: "test.string".into(),
+                    value: SavedPropertyValue::String("-3.5".into()),
+                },
+                SavedProperty {
+                    name: "test.color".into(),
+                    value: SavedPropertyValue::Color([0.1, 0.2, 0.3]),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn save_load_roundtrip() {
+        let path = test_path("roundtrip");
+        let savegame = test_savegame();
+        savegame.write(&path).unwrap();
+        let loaded = SaveGame::read(&path, &SaveGameMigrations::default());
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.version, SAVEGAME_VERSION);
+        assert_eq!(loaded.tiles.len(), 1);
+        assert_eq!(loaded.tiles[0].cube, HexCube::new(1, -1, 0));
+        assert_eq!(loaded.players[0].team, 1);
+        assert_eq!(loaded.players[0].health, savegame.players[0].health);
+        assert_eq!(loaded.turn_state.turn, 3);
+        assert!(matches!(
+            loaded.properties[0].value,
+            SavedPropertyValue::Bool(true)
+        ));
+        assert!(matches!(
+            &loaded.properties[1].value,
+            SavedPropertyValue::String(s) if s == "-3.5"
+        ));
+        assert!(matches!(
+            loaded.properties[2].value,
+            SavedPropertyValue::Color(_)
+        ));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let path = test_path("unsupported");
+        let mut savegame = test_savegame();
+        savegame.version = SAVEGAME_VERSION + 1;
+        savegame.write(&path).unwrap();
+        let loaded = SaveGame::read(&path, &SaveGameMigrations::default());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            loaded,
+            Err(SaveGameError::UnsupportedVersion(v)) if v == SAVEGAME_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn migrate_v1() {
+        let path = test_path("v1");
+        std::fs::write(
+            &path,
+            r#"(
+                version: 1,
+                materials: [],
+                tiles: [(cube: (x: 0, y: 0, z: 0), height: 0.0, material: 0)],
+                players: [(
+                    cube: (x: 0, y: 0, z: 0),
+                    translation: (0.0, 0.5, 0.0),
+                    rotation: (0.0, 0.0, 0.0, 1.0),
+                    explosion_time_left: None,
+                )],
+                turn_state: (turn: 1, active_team: 0, num_teams: 2),
+                properties: [(name: "test.bool", value: Bool(true))],
+            )"#,
+        )
+        .unwrap();
+        let loaded = SaveGame::read(&path, &SaveGameMigrations::default());
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.version, SAVEGAME_VERSION);
+        assert_eq!(loaded.players[0].team, 0);
+        assert_eq!(loaded.players[0].health, None);
+        assert!(matches!(
+            loaded.properties[0].value,
+            SavedPropertyValue::Bool(true)
+        ));
+    }
+
+    #[test]
+    fn migrate_v2() {
+        let path = test_path("v2");
+        std::fs::write(
+            &path,
+            r#"(
+                version: 2,
+                materials: [],
+                tiles: [(cube: (x: 0, y: 0, z: 0), height: 0.0, material: 0)],
+                players: [(
+                    cube: (x: 0, y: 0, z: 0),
+                    team: 1,
+                    translation: (0.0, 0.5, 0.0),
+                    rotation: (0.0, 0.0, 0.0, 1.0),
+                    explosion_time_left: Some(0.5),
+                )],
+                turn_state: (turn: 1, active_team: 1, num_teams: 2),
+                properties: [],
+            )"#,
+        )
+        .unwrap();
+        let loaded = SaveGame::read(&path, &SaveGameMigrations::default());
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.tiles[0].scorch, 0.0);
+        assert_eq!(loaded.players[0].team, 1);
+        assert_eq!(loaded.players[0].health, None);
+        assert_eq!(loaded.players[0].explosion_time_left, Some(0.5));
+    }
+
+    #[test]
+    fn migrate_v3() {
+        let path = test_path("v3");
+        std::fs::write(
+            &path,
+            r#"(
+                version: 3,
+                materials: [],
+                tiles: [(cube: (x: 1, y: -1, z: 0), height: 0.5, material: 0)],
+                players: [(
+                    cube: (x: 0, y: 0, z: 0),
+                    team: 1,
+                    health: Some((current: 30.0, max: 100.0)),
+                    translation: (0.0, 0.5, 0.0),
+                    rotation: (0.0, 0.0, 0.0, 1.0),
+                    explosion_time_left: None,
+                )],
+                turn_state: (turn: 2, active_team: 1, num_teams: 2),
+                properties: [(name: "test.string", value: String("-3.5"))],
+            )"#,
+        )
+        .unwrap();
+        let loaded = SaveGame::read(&path, &SaveGameMigrations::default());
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.version, SAVEGAME_VERSION);
+        assert_eq!(loaded.tiles[0].cube, HexCube::new(1, -1, 0));
+        assert_eq!(loaded.tiles[0].height, 0.5);
+        assert_eq!(loaded.tiles[0].scorch, 0.0);
+        assert_eq!(
+            loaded.players[0].health,
+            Some(Health {
+                current: 30.0,
+                max: 100.0,
+            })
+        );
+        assert_eq!(loaded.turn_state.turn, 2);
+        assert!(matches!(
+            &loaded.properties[0].value,
+            SavedPropertyValue::String(s) if s == "-3.5"
+        ));
+    }
+
+    fn property_string(app: &mut App, name: &str) -> Option<String> {
+        let mut query = app.world.query::<(&Name, &PropertyValue)>();
+        query
+            .iter(&app.world)
+            .find(|(n, _)| n.as_str() == name)
+            .and_then(|(_, value)| match value {
+                PropertyValue::String(s) => Some(s.clone()),
+                _ => None,
+            })
+    }
+
+    fn set_property(app: &mut App, name: &str, value: &str) {
+        app.world
+            .get_resource_mut::<Events<PropertyUpdateEvent>>()
+            .unwrap()
+            .send(PropertyUpdateEvent::new(
+                name.into(),
+                PropertyValue::String(value.into()),
+            ));
+    }
+
+    fn send<T: Send + Sync + 'static>(app: &mut App, event: T) {
+        app.world
+            .get_resource_mut::<Events<T>>()
+            .unwrap()
+            .send(event);
+    }
+
+    fn update(app: &mut App) {
+        for _ in 0..3 {
+            app.update();
+        }
+    }
+
+    // a gravity changed e.g. in the hud is saved and restored like the other properties
+    #[test]
+    fn physics_property_survives_save_load() {
+        let path = test_path("physics_property");
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<StandardMaterial>()
+            .add_asset::<Mesh>()
+            .add_plugin(crate::property::PropertyPlugin)
+            .add_plugin(SaveGamePlugin)
+            .init_resource::<GlobalState>()
+            .init_resource::<TurnState>()
+            .add_startup_system(setup_physics_properties_system);
+        update(&mut app);
+        assert_eq!(
+            property_string(&mut app, PHYSICS_GRAVITY_PROPERTY).as_deref(),
+            Some("-9.81")
+        );
+
+        set_property(&mut app, PHYSICS_GRAVITY_PROPERTY, "-3.0");
+        update(&mut app);
+        send(&mut app, SaveGameEvent(path.clone()));
+        update(&mut app);
+
+        set_property(&mut app, PHYSICS_GRAVITY_PROPERTY, "-9.81");
+        update(&mut app);
+        send(&mut app, LoadGameEvent(path.clone()));
+        update(&mut app);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            property_string(&mut app, PHYSICS_GRAVITY_PROPERTY).as_deref(),
+            Some("-3.0")
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+
+// slow motion limits
+pub const MIN_TIME_SCALE: f32 = 1.0 / 16.0;
+pub const MAX_TIME_SCALE: f32 = 1.0;
+
+// simulated time advanced by a single step while paused
+pub const STEP_DURATION: f32 = 1.0 / 60.0;
+
+// Time of the simulation (physics, fx, explosions), as opposed to Time, which keeps running for UI,
+// camera and networking. Can be paused, stepped frame by frame and slowed down, unless locked.
+pub struct SimTime {
+    pub scale: f32,
+    pub paused: bool,
+    // networked games have to keep up with their peers, pause and slow motion are disabled there
+    locked: bool,
+    step_requested: bool,
+    stepping: bool,
+    delta: f32,
+    elapsed: f64,
+}
+
+impl Default for SimTime {
+    fn default() -> Self {
+        SimTime {
+            scale: 1.0,
+            paused: false,
+            locked: false,
+            step_requested: false,
+            stepping: false,
+            delta: 0.0,
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl SimTime {
+    pub fn locked() -> Self {
+        SimTime {
+            locked: true,
+            ..default()
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta
+    }
+
+    pub fn seconds_since_startup(&self) -> f64 {
+        self.elapsed
+    }
+
+    // true if the simulation advances this frame
+    pub fn is_running(&self) -> bool {
+        !self.paused || self.stepping
+    }
+
+    // in the current frame, only has an effect while paused
+    pub fn is_stepping(&self) -> bool {
+        self.stepping
+    }
+
+    // advance a single frame while paused
+    pub fn request_step(&mut self) {
+        self.step_requested = !self.locked;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused && !self.locked;
+    }
+
+    pub fn slower(&mut self) {
+        if !self.locked {
+            self.scale = (self.scale * 0.5).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        }
+    }
+
+    pub fn faster(&mut self) {
+        if !self.locked {
+            self.scale = (self.scale * 2.0).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        }
+    }
+}
+
+pub fn sim_time_system(time: Res<Time>, mut sim_time: ResMut<SimTime>) {
+    sim_time.stepping = sim_time.paused && sim_time.step_requested;
+    sim_time.step_requested = false;
+    sim_time.delta = if sim_time.stepping {
+        STEP_DURATION
+    } else if sim_time.paused {
+        0.0
+    } else {
+        time.delta_seconds() * sim_time.scale
+    };
+    sim_time.elapsed += sim_time.delta as f64;
+}
+
+// P: pause, period: single step, brackets: slower / faster
+pub fn sim_time_keyboard_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut sim_time: ResMut<SimTime>,
+) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        sim_time.toggle_pause();
+    }
+    if keyboard_input.just_pressed(KeyCode::Period) {
+        sim_time.request_step();
+    }
+    if keyboard_input.just_pressed(KeyCode::LBracket) {
+        sim_time.slower();
+    }
+    if keyboard_input.just_pressed(KeyCode::RBracket) {
+        sim_time.faster();
+    }
+}
+
+pub struct SimTimePlugin;
+
+impl Plugin for SimTimePlugin {
+    fn build(&self, app: &mut App) {
+        // Time itself is updated in CoreStage::First
+        app.init_resource::<SimTime>()
+            .add_system_to_stage(CoreStage::PreUpdate, sim_time_system);
+    }
+}